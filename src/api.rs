@@ -0,0 +1,363 @@
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use eframe::egui::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tiny_http::{Method, Request};
+
+use crate::{AppMsg, Schedule, http};
+
+/// Largest request body accepted, far above any schedule.
+const MAX_BODY: u64 = 1024 * 1024;
+/// How long a request waits for the window to handle it.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The control API on localhost, shared by all schedules.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// Expected as `Authorization: Bearer <token>` on every request.
+    pub token: String,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8421,
+            token: String::new(),
+        }
+    }
+}
+
+/// 32 random bytes, hex-encoded.
+pub fn new_token() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(hex::encode(bytes))
+}
+
+/// A call the window carries out on its own state, like the matching button.
+pub enum ApiRequest {
+    List,
+    Get(u64),
+    Status,
+    /// A schedule as JSON; missing fields take their defaults.
+    Add(Value),
+    /// Fields to change, merged into the schedule with this id.
+    Edit(u64, Value),
+    Delete(u64),
+    Run(u64),
+}
+
+pub struct ApiReply {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl ApiReply {
+    pub fn ok(status: u16, body: Value) -> Self {
+        Self { status, body }
+    }
+
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.into() }),
+        }
+    }
+}
+
+/// The running API. Dropping it, which the window does, answers a call
+/// still waiting for the window instead of waiting for it.
+pub struct ApiServer {
+    stopping: Arc<AtomicBool>,
+    _http: http::Server,
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        // Runs before the listener is joined.
+        self.stopping.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Serve the API on `127.0.0.1:<port>`, handing each authorized call to the
+/// window over `tx` and waking it through `ctx`.
+pub fn serve(
+    settings: &ApiSettings,
+    tx: Sender<AppMsg>,
+    ctx: Option<Context>,
+) -> anyhow::Result<ApiServer> {
+    let token = settings.token.clone();
+    let stopping = Arc::new(AtomicBool::new(false));
+    let stop = stopping.clone();
+    let http = http::Server::start(
+        &format!("127.0.0.1:{}", settings.port),
+        move |mut request| {
+            let reply = if !authorized(&request, &token) {
+                ApiReply::error(401, "Missing or wrong token")
+            } else {
+                match parse(&mut request) {
+                    Ok(call) => dispatch(call, &tx, ctx.as_ref(), &stop),
+                    Err(reply) => reply,
+                }
+            };
+            http::respond(
+                request,
+                reply.status,
+                "application/json",
+                reply.body.to_string(),
+            );
+        },
+    )?;
+    Ok(ApiServer {
+        stopping,
+        _http: http,
+    })
+}
+
+fn dispatch(
+    call: ApiRequest,
+    tx: &Sender<AppMsg>,
+    ctx: Option<&Context>,
+    stopping: &AtomicBool,
+) -> ApiReply {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    if tx.send(AppMsg::Api(call, reply_tx)).is_err() {
+        return ApiReply::error(503, "AutoBackup is shutting down");
+    }
+    if let Some(ctx) = ctx {
+        ctx.request_repaint();
+    }
+    // Waits in slices: the window may be the one stopping this server.
+    let started = Instant::now();
+    loop {
+        match reply_rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(reply) => return reply,
+            Err(RecvTimeoutError::Disconnected) => {
+                return ApiReply::error(503, "AutoBackup is shutting down");
+            }
+            Err(RecvTimeoutError::Timeout) if stopping.load(Ordering::Relaxed) => {
+                return ApiReply::error(503, "The control API is restarting");
+            }
+            Err(RecvTimeoutError::Timeout) if started.elapsed() >= REPLY_TIMEOUT => {
+                return ApiReply::error(503, "AutoBackup did not answer in time");
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+    }
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    let Some(given) = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    // Compare every byte so the time taken does not hint at the token.
+    !token.is_empty()
+        && given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn parse(request: &mut Request) -> Result<ApiRequest, ApiReply> {
+    let path = request.url().split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path
+        .trim_matches('/')
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+    let id = |s: &str| {
+        s.parse::<u64>()
+            .map_err(|_| ApiReply::error(404, format!("Not a schedule id: {s}")))
+    };
+    let method = request.method().clone();
+    match (segments.as_slice(), method) {
+        (["api", "status"], Method::Get) => Ok(ApiRequest::Status),
+        (["api", "schedules"], Method::Get) => Ok(ApiRequest::List),
+        (["api", "schedules"], Method::Post) => Ok(ApiRequest::Add(schedule_body(request)?)),
+        (["api", "schedules", s], Method::Get) => Ok(ApiRequest::Get(id(s)?)),
+        (["api", "schedules", s], Method::Patch | Method::Put) => {
+            Ok(ApiRequest::Edit(id(s)?, schedule_body(request)?))
+        }
+        (["api", "schedules", s], Method::Delete) => Ok(ApiRequest::Delete(id(s)?)),
+        (["api", "schedules", s, "run"], Method::Post) => Ok(ApiRequest::Run(id(s)?)),
+        (["api", "status"] | ["api", "schedules", ..], _) => {
+            Err(ApiReply::error(405, "Method not allowed"))
+        }
+        _ => Err(ApiReply::error(404, "Not found")),
+    }
+}
+
+/// The request body as a JSON object.
+fn body(request: &mut Request) -> Result<Value, ApiReply> {
+    let mut text = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY)
+        .read_to_string(&mut text)
+        .map_err(|e| ApiReply::error(400, format!("Failed to read the body: {e}")))?;
+    match serde_json::from_str(&text) {
+        Ok(value @ Value::Object(_)) => Ok(value),
+        Ok(_) => Err(ApiReply::error(400, "Expected a JSON object")),
+        Err(e) => Err(ApiReply::error(400, format!("Invalid JSON: {e}"))),
+    }
+}
+
+/// A schedule body, which may not touch the hook commands: they run as
+/// shell commands, so only the window sets them.
+fn schedule_body(request: &mut Request) -> Result<Value, ApiReply> {
+    let value = body(request)?;
+    if value.get("hooks").is_some() {
+        return Err(ApiReply::error(
+            403,
+            "Hooks can only be set in the AutoBackup window",
+        ));
+    }
+    Ok(value)
+}
+
+/// `s` as sent to clients: its settings without secrets or hooks, plus its
+/// state.
+pub fn schedule_json(s: &Schedule, queued: bool, last_success: Option<i64>) -> Value {
+    let mut value = serde_json::to_value(s).unwrap_or_default();
+    // Left out rather than blanked so a client sending the object back
+    // keeps them.
+    for (section, key) in [("s3", "secret_key"), ("webdav", "password")] {
+        if let Some(Value::Object(section)) = value.get_mut(section) {
+            section.remove(key);
+        }
+    }
+    if let Value::Object(fields) = &mut value {
+        fields.remove("hooks");
+    }
+    value["status"] = json!({
+        "running": s.is_running,
+        "queued": queued,
+        "last_run": s.last_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        "last_success": last_success,
+    });
+    value
+}
+
+/// Merge the fields of `patch` into `target`, recursing into objects so a
+/// partial section leaves the rest of it alone.
+pub fn merge(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tiny_http::{Header, TestRequest};
+
+    use super::*;
+
+    fn request(method: Method, path: &str, body: &'static str) -> Request {
+        TestRequest::new()
+            .with_method(method)
+            .with_path(path)
+            .with_body(body)
+            .into()
+    }
+
+    fn status(result: Result<ApiRequest, ApiReply>) -> u16 {
+        result.err().map_or(200, |reply| reply.status)
+    }
+
+    fn with_auth(value: &str) -> Request {
+        TestRequest::new()
+            .with_header(Header::from_bytes("Authorization", value).unwrap())
+            .into()
+    }
+
+    #[test]
+    fn routes() {
+        let parsed = parse(&mut request(Method::Get, "/api/schedules/12?x=1", ""));
+        assert!(matches!(parsed, Ok(ApiRequest::Get(12))));
+        let parsed = parse(&mut request(Method::Post, "/api/schedules/3/run", ""));
+        assert!(matches!(parsed, Ok(ApiRequest::Run(3))));
+        let parsed = parse(&mut request(
+            Method::Patch,
+            "/api/schedules/3",
+            r#"{"period_hours": 6}"#,
+        ));
+        assert!(matches!(parsed, Ok(ApiRequest::Edit(3, v)) if v["period_hours"] == 6));
+        assert_eq!(
+            status(parse(&mut request(Method::Delete, "/api/status", ""))),
+            405
+        );
+        assert_eq!(
+            status(parse(&mut request(Method::Get, "/api/schedules/abc", ""))),
+            404
+        );
+        assert_eq!(
+            status(parse(&mut request(Method::Get, "/metrics", ""))),
+            404
+        );
+    }
+
+    #[test]
+    fn schedule_bodies_are_checked() {
+        let add = |body| status(parse(&mut request(Method::Post, "/api/schedules", body)));
+        assert_eq!(add(r#"{"source_dir": "/home/me/Documents"}"#), 200);
+        assert_eq!(add("[1, 2]"), 400);
+        assert_eq!(add("{"), 400);
+        assert_eq!(add(r#"{"hooks": {"pre": "rm -rf ~"}}"#), 403);
+    }
+
+    #[test]
+    fn only_the_exact_bearer_token_is_authorized() {
+        assert!(authorized(&with_auth("Bearer secret"), "secret"));
+        assert!(!authorized(&with_auth("Bearer secreT"), "secret"));
+        assert!(!authorized(&with_auth("Bearer secret2"), "secret"));
+        assert!(!authorized(&with_auth("secret"), "secret"));
+        assert!(!authorized(&with_auth("Bearer "), ""));
+        assert!(!authorized(&request(Method::Get, "/", ""), "secret"));
+    }
+
+    #[test]
+    fn merge_keeps_fields_the_patch_leaves_out() {
+        let mut target = json!({
+            "name": "Documents",
+            "period_hours": 24,
+            "s3": { "bucket": "old", "region": "eu-central-1", "secret_key": "hidden" },
+        });
+        merge(
+            &mut target,
+            json!({ "period_hours": 6, "s3": { "bucket": "new" } }),
+        );
+        assert_eq!(
+            target,
+            json!({
+                "name": "Documents",
+                "period_hours": 6,
+                "s3": { "bucket": "new", "region": "eu-central-1", "secret_key": "hidden" },
+            })
+        );
+        // The merged value still reads back as a schedule.
+        let s: Schedule = serde_json::from_value(target).unwrap();
+        assert_eq!((s.period_hours, s.s3.bucket.as_str()), (6, "new"));
+        assert_eq!(s.s3.secret_key, "hidden");
+    }
+}
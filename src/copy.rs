@@ -0,0 +1,648 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::destination::{self, CopyMethod, Destination};
+use crate::journal::{self, Journal};
+use crate::logfile::Level;
+use crate::report::{self, Action, RunReport};
+use crate::source::SNAPSHOT_PREFIX;
+use crate::{AppMsg, Schedule, parse_skip_tokens};
+
+/// What to do with symbolic links found in the source.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SymlinkPolicy {
+    /// Recreate the link itself. Destinations without symlinks follow instead.
+    #[default]
+    CopyLink,
+    Follow,
+    Skip,
+}
+
+impl SymlinkPolicy {
+    pub const ALL: [SymlinkPolicy; 3] = [
+        SymlinkPolicy::CopyLink,
+        SymlinkPolicy::Follow,
+        SymlinkPolicy::Skip,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SymlinkPolicy::CopyLink => "Copy links",
+            SymlinkPolicy::Follow => "Follow links",
+            SymlinkPolicy::Skip => "Skip links",
+        }
+    }
+}
+
+/// Longest wait before retrying a file, however far the backoff has grown.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Per-run copy settings derived from a `Schedule`.
+pub struct CopyOptions {
+    pub skip_exts: Vec<String>,
+    pub skip_folders: Vec<String>,
+    /// Delete destination entries that no longer exist in the source.
+    pub mirror: bool,
+    pub symlinks: SymlinkPolicy,
+    pub retries: u32,
+    pub retry_delay: Duration,
+}
+
+impl CopyOptions {
+    pub fn from_schedule(s: &Schedule) -> Self {
+        Self {
+            skip_exts: parse_skip_tokens(&s.skip_file_exts_label),
+            skip_folders: s
+                .skip_folders_label
+                .split_whitespace()
+                .map(|s| s.to_ascii_lowercase())
+                .collect(),
+            mirror: s.mirror,
+            symlinks: s.symlinks,
+            retries: s.retries,
+            retry_delay: Duration::from_secs(s.retry_delay_secs),
+        }
+    }
+
+    pub fn skips_folder(&self, name: &str) -> bool {
+        self.folder_rule(name).is_some()
+    }
+
+    /// The rule skipping a folder name, for reports.
+    pub fn folder_rule(&self, name: &str) -> Option<String> {
+        if name.starts_with(SNAPSHOT_PREFIX) {
+            return Some("snapshot folder".to_owned());
+        }
+        self.skip_folders
+            .iter()
+            .find(|f| f.eq_ignore_ascii_case(name))
+            .map(|f| format!("folder rule {f}"))
+    }
+
+    pub fn skips_file(&self, path: &Path) -> bool {
+        self.file_rule(path).is_some()
+    }
+
+    /// The rule skipping a file, for reports.
+    pub fn file_rule(&self, path: &Path) -> Option<String> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        if ext.is_empty() {
+            return None;
+        }
+        self.skip_exts
+            .iter()
+            .find(|e| **e == ext)
+            .map(|e| format!("extension rule *.{e}"))
+    }
+
+    /// Whether a path relative to the source falls under a skip rule.
+    pub fn is_skipped(&self, rel: &Path) -> bool {
+        rel.components()
+            .any(|c| self.skips_folder(&c.as_os_str().to_string_lossy()))
+            || self.skips_file(rel)
+    }
+}
+
+/// Walks a source tree and writes it through a `Destination`.
+pub struct Copier<'a> {
+    dest: &'a mut dyn Destination,
+    opts: &'a CopyOptions,
+    tx: &'a Sender<AppMsg>,
+    /// Canonical paths of the directories currently being copied. A followed
+    /// symlink resolving to one of them would recurse forever.
+    ancestors: Vec<PathBuf>,
+    /// First destination path written for each multiply linked source file.
+    links: HashMap<(u64, u64), PathBuf>,
+    /// Progress of a full run, so an interrupted one can pick up again.
+    journal: Option<&'a mut Journal>,
+    report: RunReport,
+}
+
+impl<'a> Copier<'a> {
+    pub fn new(
+        dest: &'a mut dyn Destination,
+        opts: &'a CopyOptions,
+        tx: &'a Sender<AppMsg>,
+    ) -> Self {
+        Self {
+            dest,
+            opts,
+            tx,
+            ancestors: Vec::new(),
+            links: HashMap::new(),
+            journal: None,
+            report: RunReport::default(),
+        }
+    }
+
+    pub fn with_journal(mut self, journal: &'a mut Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    pub fn into_report(self) -> RunReport {
+        self.report
+    }
+
+    pub fn dest(&mut self) -> &mut dyn Destination {
+        self.dest
+    }
+
+    fn log(&self, level: Level, msg: String) {
+        let _ = self.tx.send(AppMsg::Log(level, msg));
+    }
+
+    pub fn copy_recursive(&mut self, source: &Path, rel: &Path) -> anyhow::Result<()> {
+        // Ensure destination exists
+        self.dest.create_dir_all(rel)?;
+
+        self.ancestors
+            .push(fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf()));
+        let result = self.copy_children(source, rel);
+        self.ancestors.pop();
+        result
+    }
+
+    fn copy_children(&mut self, source: &Path, rel: &Path) -> anyhow::Result<()> {
+        let mut seen = HashSet::new();
+        for entry_res in fs::read_dir(source)? {
+            let entry = entry_res?;
+            let file_name = entry.file_name();
+            seen.insert(file_name.to_string_lossy().to_string());
+            self.copy_entry(&entry.path(), &rel.join(&file_name))?;
+        }
+
+        // Mirror: drop what was deleted from the source. Skipped entries still
+        // exist in the source and are left alone.
+        if self.opts.mirror {
+            for name in self.dest.list_dir(rel)? {
+                if seen.contains(&name)
+                    || (rel.as_os_str().is_empty()
+                        && (name == destination::COMPLETE_MARKER || name == report::REPORTS_DIR))
+                {
+                    continue;
+                }
+                let stale = rel.join(&name);
+                match self.dest.remove(&stale) {
+                    Ok(()) => {
+                        self.report.record(Action::Deleted, &stale, None, "");
+                        self.log(
+                            Level::Info,
+                            format!("Deleted {}", self.dest.display_path(&stale)),
+                        );
+                    }
+                    Err(e) => {
+                        self.report
+                            .record(Action::Failed, &stale, None, &format!("delete: {e}"));
+                        self.log(
+                            Level::Error,
+                            format!("Failed to delete {}: {}", self.dest.display_path(&stale), e),
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy one source entry (file, directory or symlink) to `rel`. Failures
+    /// on single files are logged; directory read errors abort the run.
+    pub fn copy_entry(&mut self, path: &Path, rel: &Path) -> anyhow::Result<()> {
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut meta = fs::symlink_metadata(path)?;
+
+        if meta.file_type().is_symlink() {
+            match self.opts.symlinks {
+                SymlinkPolicy::Skip => {
+                    self.report.record(Action::Skipped, rel, None, "symlink");
+                    return Ok(());
+                }
+                SymlinkPolicy::CopyLink if self.dest.supports_symlinks() => {
+                    self.copy_symlink(path, rel, &meta);
+                    return Ok(());
+                }
+                _ => match fs::metadata(path) {
+                    Ok(target) => meta = target,
+                    Err(e) => {
+                        self.report
+                            .record(Action::Skipped, rel, None, "broken link");
+                        self.log(
+                            Level::Warn,
+                            format!("Skipping broken link {}: {e}", path.display()),
+                        );
+                        return Ok(());
+                    }
+                },
+            }
+        }
+
+        if meta.is_dir() {
+            // folder skip check
+            if let Some(rule) = self.opts.folder_rule(&file_name) {
+                self.report.record(Action::Skipped, rel, None, &rule);
+                return Ok(());
+            }
+            let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+            if self.ancestors.contains(&canonical) {
+                self.report
+                    .record(Action::Skipped, rel, None, "symlink loop");
+                self.log(
+                    Level::Warn,
+                    format!("Skipping symlink loop at {}", path.display()),
+                );
+                return Ok(());
+            }
+            self.copy_recursive(path, rel)?;
+            // Directory times change while children are written, so they are
+            // applied last.
+            if let Err(e) = self.dest.set_metadata(path, &meta, rel) {
+                self.log(
+                    Level::Warn,
+                    format!(
+                        "Failed to set metadata on {}: {}",
+                        self.dest.display_path(rel),
+                        e
+                    ),
+                );
+            }
+        } else if meta.is_file() {
+            // ext skip
+            if let Some(rule) = self.opts.file_rule(path) {
+                self.report
+                    .record(Action::Skipped, rel, Some(meta.len()), &rule);
+                return Ok(());
+            }
+            self.copy_file(path, rel, &meta);
+        } else if let Some(kind) = special_kind(&meta.file_type()) {
+            self.report.record_special(path, rel, kind);
+        }
+        Ok(())
+    }
+
+    fn copy_file(&mut self, path: &Path, rel: &Path, meta: &fs::Metadata) {
+        let key = link_key(meta);
+        if let Some(first) = key.and_then(|k| self.links.get(&k)).cloned() {
+            match self.dest.hard_link(&first, rel) {
+                Ok(true) => {
+                    self.report.hard_links += 1;
+                    self.report.record(
+                        Action::Linked,
+                        rel,
+                        Some(meta.len()),
+                        &format!("to {}", first.display()),
+                    );
+                    return;
+                }
+                // Not supported by the destination: store a full copy.
+                Ok(false) => {}
+                Err(e) => self.log(
+                    Level::Error,
+                    format!(
+                        "Failed to link {} -> {}: {}",
+                        self.dest.display_path(rel),
+                        self.dest.display_path(&first),
+                        e
+                    ),
+                ),
+            }
+        }
+
+        // Finished by the interrupted run this one resumes
+        if self.journal.as_ref().is_some_and(|j| j.is_done(rel, meta)) {
+            self.report.files_resumed += 1;
+            if let Some(k) = key {
+                self.links.insert(k, rel.to_path_buf());
+            }
+            return;
+        }
+
+        // Incremental: leave files the destination already has
+        if let Ok(existing) = self.dest.stat(rel)
+            && destination::is_up_to_date(meta, existing.as_ref())
+        {
+            self.report.files_unchanged += 1;
+            if let Some(k) = key {
+                self.links.insert(k, rel.to_path_buf());
+            }
+            return;
+        }
+        // Copy, retrying files that fail (locked, busy) or change underneath us
+        let mut meta = meta.clone();
+        let mut attempt = 0;
+        let mut still_changing = false;
+        let copied = loop {
+            let copied = self.put(path, rel, &meta).and_then(|method| {
+                self.dest.set_metadata(path, &meta, rel)?;
+                Ok(method)
+            });
+            let changed = match &copied {
+                Ok(_) => fs::metadata(path)
+                    .ok()
+                    .filter(|now| has_changed(&meta, now)),
+                Err(_) => None,
+            };
+            if copied.is_ok() && changed.is_none() {
+                break copied;
+            }
+            // A missing file, a denied read or a full destination will not
+            // go away by waiting.
+            let retryable = match &copied {
+                Ok(_) => true,
+                Err(e) => is_transient(e),
+            };
+            if attempt == self.opts.retries || !retryable {
+                if changed.is_some() {
+                    self.report.record_changed(path);
+                    still_changing = true;
+                }
+                break copied;
+            }
+            let delay = (self.opts.retry_delay * 2u32.pow(attempt)).min(MAX_RETRY_DELAY);
+            attempt += 1;
+            self.report.retries += 1;
+            match (&copied, changed) {
+                (Err(e), _) => self.log(
+                    Level::Warn,
+                    format!("Retrying {} in {}s: {}", path.display(), delay.as_secs(), e),
+                ),
+                (Ok(_), Some(now)) => {
+                    self.log(
+                        Level::Warn,
+                        format!(
+                            "{} changed while being copied, copying again in {}s",
+                            path.display(),
+                            delay.as_secs()
+                        ),
+                    );
+                    meta = now;
+                }
+                (Ok(_), None) => {}
+            }
+            thread::sleep(delay);
+        };
+        let meta = &meta;
+        match copied {
+            Ok(method) => {
+                let detail = match method {
+                    CopyMethod::Reflink => {
+                        self.report.reflinked += 1;
+                        "reflink"
+                    }
+                    CopyMethod::Sparse => {
+                        self.report.sparse_copies += 1;
+                        "sparse"
+                    }
+                    CopyMethod::Full => "",
+                };
+                let detail = if still_changing {
+                    "changed during copy"
+                } else {
+                    detail
+                };
+                self.report
+                    .record(Action::Copied, rel, Some(meta.len()), detail);
+                self.report.files_copied += 1;
+                self.report.bytes_copied += meta.len();
+                if let Some(k) = key {
+                    self.links.insert(k, rel.to_path_buf());
+                }
+                if let Some(journal) = self.journal.as_deref_mut()
+                    && let Err(e) = journal.finish(rel, meta)
+                {
+                    self.log(Level::Error, format!("Failed to save run journal: {e}"));
+                }
+            }
+            Err(e) => {
+                self.report.files_failed += 1;
+                self.report
+                    .record(Action::Failed, rel, Some(meta.len()), &e.to_string());
+                self.log(
+                    Level::Error,
+                    format!(
+                        "Failed to copy {} -> {}: {}",
+                        path.display(),
+                        self.dest.display_path(rel),
+                        e
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Write the file contents, continuing a large file the interrupted run
+    /// had partly written.
+    fn put(&mut self, path: &Path, rel: &Path, meta: &fs::Metadata) -> anyhow::Result<CopyMethod> {
+        let Some(journal) = self.journal.as_deref_mut() else {
+            return self.dest.put_file(path, rel);
+        };
+        if meta.len() < journal::LARGE_FILE {
+            return self.dest.put_file(path, rel);
+        }
+        if journal.is_current(rel, meta)
+            && let Some(offset) = self.dest.partial_len(rel)?
+            && offset > 0
+            && offset <= meta.len()
+        {
+            self.log(
+                Level::Info,
+                format!(
+                    "Resuming {} at {} of {} bytes",
+                    path.display(),
+                    offset,
+                    meta.len()
+                ),
+            );
+            return self.dest.resume_file(path, rel, offset);
+        }
+        journal.begin(rel, meta)?;
+        self.dest.put_file(path, rel)
+    }
+
+    fn copy_symlink(&mut self, path: &Path, rel: &Path, meta: &fs::Metadata) {
+        let copied = fs::read_link(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|target| self.dest.put_symlink(&target, rel))
+            .and_then(|()| self.dest.set_metadata(path, meta, rel));
+        match &copied {
+            Ok(()) => self.report.record(Action::Copied, rel, None, "symlink"),
+            Err(e) => self
+                .report
+                .record(Action::Failed, rel, None, &format!("symlink: {e}")),
+        }
+        if let Err(e) = copied {
+            self.log(
+                Level::Error,
+                format!(
+                    "Failed to copy link {} -> {}: {}",
+                    path.display(),
+                    self.dest.display_path(rel),
+                    e
+                ),
+            );
+        }
+    }
+}
+
+/// The size of a run, following the same skip rules and link handling as
+/// `Copier`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunSize {
+    /// Bytes of every file the destination does not already have. Replaced
+    /// files are counted in full because the new copy is written before the
+    /// old one goes.
+    pub to_copy: u64,
+    /// Bytes of every file the backup holds once the run is done.
+    pub total: u64,
+}
+
+pub fn run_size(
+    source: &Path,
+    dest: &mut dyn Destination,
+    opts: &CopyOptions,
+) -> anyhow::Result<RunSize> {
+    let mut scan = Scan {
+        dest,
+        opts,
+        ancestors: Vec::new(),
+        links: HashSet::new(),
+        size: RunSize::default(),
+    };
+    scan.dir(source, Path::new(""))?;
+    Ok(scan.size)
+}
+
+struct Scan<'a> {
+    dest: &'a mut dyn Destination,
+    opts: &'a CopyOptions,
+    ancestors: Vec<PathBuf>,
+    links: HashSet<(u64, u64)>,
+    size: RunSize,
+}
+
+impl Scan<'_> {
+    fn dir(&mut self, source: &Path, rel: &Path) -> anyhow::Result<()> {
+        self.ancestors
+            .push(fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf()));
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            self.entry(&entry.path(), &rel.join(entry.file_name()))?;
+        }
+        self.ancestors.pop();
+        Ok(())
+    }
+
+    fn entry(&mut self, path: &Path, rel: &Path) -> anyhow::Result<()> {
+        let mut meta = fs::symlink_metadata(path)?;
+        if meta.file_type().is_symlink() {
+            match self.opts.symlinks {
+                SymlinkPolicy::Skip => return Ok(()),
+                SymlinkPolicy::CopyLink if self.dest.supports_symlinks() => return Ok(()),
+                _ => match fs::metadata(path) {
+                    Ok(target) => meta = target,
+                    Err(_) => return Ok(()),
+                },
+            }
+        }
+        if meta.is_dir() {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+            if self.opts.skips_folder(&name) || self.ancestors.contains(&canonical) {
+                return Ok(());
+            }
+            self.dir(path, rel)?;
+        } else if meta.is_file() && !self.opts.skips_file(path) {
+            if link_key(&meta).is_some_and(|k| !self.links.insert(k)) {
+                return Ok(());
+            }
+            let existing = self.dest.stat(rel).ok().flatten();
+            if !destination::is_up_to_date(&meta, existing.as_ref()) {
+                self.size.to_copy += meta.len();
+            }
+            self.size.total += meta.len();
+        }
+        Ok(())
+    }
+}
+
+/// Whether an error is one a later attempt may not hit: the file was busy or
+/// locked by another program, or the call was interrupted.
+fn is_transient(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|e| {
+            matches!(
+                e.kind(),
+                ErrorKind::Interrupted
+                    | ErrorKind::WouldBlock
+                    | ErrorKind::ResourceBusy
+                    | ErrorKind::ExecutableFileBusy
+            ) || is_sharing_violation(e)
+        })
+}
+
+/// ERROR_SHARING_VIOLATION or ERROR_LOCK_VIOLATION: another program has the
+/// file open without sharing it.
+#[cfg(windows)]
+fn is_sharing_violation(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(32 | 33))
+}
+
+#[cfg(not(windows))]
+fn is_sharing_violation(_e: &io::Error) -> bool {
+    false
+}
+
+/// Whether a file's size or modification time moved between two reads.
+fn has_changed(before: &fs::Metadata, after: &fs::Metadata) -> bool {
+    before.len() != after.len() || before.modified().ok() != after.modified().ok()
+}
+
+/// Device and inode of a file that has more than one hard link.
+#[cfg(unix)]
+fn link_key(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (meta.nlink() > 1).then(|| (meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn link_key(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+fn special_kind(ft: &fs::FileType) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+    if ft.is_fifo() {
+        Some("FIFO")
+    } else if ft.is_socket() {
+        Some("socket")
+    } else if ft.is_block_device() {
+        Some("block device")
+    } else if ft.is_char_device() {
+        Some("character device")
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn special_kind(_ft: &fs::FileType) -> Option<&'static str> {
+    None
+}
@@ -0,0 +1,335 @@
+mod local;
+mod s3;
+mod sftp;
+mod webdav;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Schedule;
+use crate::throttle::Limiter;
+
+pub use local::LocalDestination;
+pub use s3::{S3Config, S3Destination};
+pub use sftp::{SftpConfig, SftpDestination};
+pub use webdav::{WebDavConfig, WebDavDestination};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DestKind {
+    #[default]
+    Local,
+    Sftp,
+    S3,
+    WebDav,
+}
+
+impl DestKind {
+    pub const ALL: [DestKind; 4] = [
+        DestKind::Local,
+        DestKind::Sftp,
+        DestKind::S3,
+        DestKind::WebDav,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DestKind::Local => "Local folder",
+            DestKind::Sftp => "SFTP",
+            DestKind::S3 => "S3",
+            DestKind::WebDav => "WebDAV",
+        }
+    }
+
+    pub fn is_remote(self) -> bool {
+        self != DestKind::Local
+    }
+}
+
+/// What to do before a run when a local destination looks too small for it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpaceCheck {
+    Off,
+    /// Log a warning and copy anyway. The default, so schedules saved before
+    /// the check existed keep running as they did.
+    #[default]
+    Warn,
+    Refuse,
+}
+
+impl SpaceCheck {
+    pub const ALL: [SpaceCheck; 3] = [SpaceCheck::Off, SpaceCheck::Warn, SpaceCheck::Refuse];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SpaceCheck::Off => "No space check",
+            SpaceCheck::Warn => "Warn if space is short",
+            SpaceCheck::Refuse => "Refuse if space is short",
+        }
+    }
+}
+
+/// What a destination knows about an entry, enough for incremental checks.
+#[derive(Clone, Copy, Debug)]
+pub struct EntryStat {
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch.
+    pub mtime: i64,
+    pub is_dir: bool,
+}
+
+/// How a file's contents reached the destination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyMethod {
+    /// Copy-on-write clone sharing extents with the source (Btrfs, XFS).
+    Reflink,
+    /// Only the data ranges were written; holes stayed holes.
+    Sparse,
+    Full,
+}
+
+/// A place backups are written to. Paths are relative to the destination root
+/// so `copy_recursive` can walk the source once for every backend.
+pub trait Destination {
+    /// Human readable root, e.g. `/home/me/BackUp` or `sftp://me@host:22/srv`.
+    fn describe(&self) -> String;
+
+    fn display_path(&self, rel: &Path) -> String {
+        format!("{}/{}", self.describe(), rel.display())
+    }
+
+    fn create_dir_all(&mut self, rel: &Path) -> anyhow::Result<()>;
+
+    /// `Ok(None)` when the entry does not exist.
+    fn stat(&mut self, rel: &Path) -> anyhow::Result<Option<EntryStat>>;
+
+    /// Write `src` to `rel` so that an interrupted run leaves either the old
+    /// file or the new one, never a truncated copy. Local and SFTP write a
+    /// `partial_path` sibling and rename it; S3 and WebDAV servers only expose
+    /// an upload once it has finished.
+    fn put_file(&mut self, src: &Path, rel: &Path) -> anyhow::Result<CopyMethod>;
+
+    /// Bytes an interrupted run left in the `partial_path` of `rel`, or `None`
+    /// when there is nothing to resume.
+    fn partial_len(&mut self, _rel: &Path) -> anyhow::Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Finish an interrupted `put_file`, keeping the first `offset` bytes of
+    /// the partial file.
+    fn resume_file(&mut self, src: &Path, rel: &Path, _offset: u64) -> anyhow::Result<CopyMethod> {
+        self.put_file(src, rel)
+    }
+
+    /// Names of the entries directly under `rel`, used by mirror mode.
+    fn list_dir(&mut self, rel: &Path) -> anyhow::Result<Vec<String>>;
+
+    /// Delete a file or a whole directory tree.
+    fn remove(&mut self, rel: &Path) -> anyhow::Result<()>;
+
+    /// Apply the source's times, permissions, ownership and extended
+    /// attributes to `rel`, as far as the backend can store them.
+    fn set_metadata(
+        &mut self,
+        _src: &Path,
+        _meta: &fs::Metadata,
+        _rel: &Path,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Make `rel` another name for the already written `existing`. Returns
+    /// `Ok(false)` when the backend has no hard links.
+    fn hard_link(&mut self, _existing: &Path, _rel: &Path) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+
+    /// Cap the backend's throughput. Backends without it copy at full speed.
+    fn set_limiter(&mut self, _limiter: Limiter) {}
+
+    fn supports_symlinks(&self) -> bool {
+        false
+    }
+
+    fn put_symlink(&mut self, _target: &Path, _rel: &Path) -> anyhow::Result<()> {
+        anyhow::bail!("{} does not support symlinks", self.describe())
+    }
+}
+
+pub fn open(s: &Schedule) -> anyhow::Result<Box<dyn Destination>> {
+    let mut dest: Box<dyn Destination> = match s.dest_kind {
+        DestKind::Local => Box::new(LocalDestination::open(Path::new(&s.dest_dir))?),
+        DestKind::Sftp => Box::new(SftpDestination::connect(&s.sftp)?),
+        DestKind::S3 => Box::new(S3Destination::connect(&s.s3)?),
+        DestKind::WebDav => Box::new(WebDavDestination::connect(&s.webdav)?),
+    };
+    if let Some(limiter) = Limiter::new(&s.throttle) {
+        dest.set_limiter(limiter);
+    }
+    Ok(dest)
+}
+
+/// Written to the destination root after a run finished without failures. A
+/// destination without it holds an interrupted or partly failed backup.
+pub const COMPLETE_MARKER: &str = ".auto_backup_complete";
+
+/// Hidden sibling a file is written to before being renamed over `rel`.
+pub fn partial_path(rel: &Path) -> PathBuf {
+    let name = rel
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    rel.with_file_name(format!(".{name}.partial"))
+}
+
+pub fn clear_complete(dest: &mut dyn Destination) -> anyhow::Result<()> {
+    let marker = Path::new(COMPLETE_MARKER);
+    if dest.stat(marker)?.is_some() {
+        dest.remove(marker)?;
+    }
+    Ok(())
+}
+
+pub fn mark_complete(dest: &mut dyn Destination, contents: &str) -> anyhow::Result<()> {
+    put_text(dest, Path::new(COMPLETE_MARKER), contents)
+}
+
+/// Write a small generated file to `rel` through a temp file.
+pub fn put_text(dest: &mut dyn Destination, rel: &Path, contents: &str) -> anyhow::Result<()> {
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    let local = std::env::temp_dir().join(format!(
+        "auto_backup_{}_{}.tmp",
+        std::process::id(),
+        SEQ.fetch_add(1, Ordering::Relaxed),
+    ));
+    fs::write(&local, contents)?;
+    let result = dest.put_file(&local, rel);
+    let _ = fs::remove_file(&local);
+    result.map(|_| ())
+}
+
+pub fn mtime_secs(meta: &fs::Metadata) -> i64 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+pub fn atime_secs(meta: &fs::Metadata) -> i64 {
+    meta.accessed()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path`,
+/// or the nearest existing folder above it.
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> anyhow::Result<u64> {
+    let Some(dir) = path.ancestors().find(|p| p.exists()) else {
+        anyhow::bail!("{} does not exist", path.display());
+    };
+    let c_path = std::ffi::CString::new(dir.as_os_str().as_encoded_bytes())?;
+    // SAFETY: `stat` is plain data and statvfs(3) only writes into it.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // the field types differ between targets
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> anyhow::Result<u64> {
+    anyhow::bail!("Free space checks are only supported on Unix")
+}
+
+/// Whether two paths, or the nearest existing folders above them, are on the
+/// same filesystem. Assumed so when that cannot be told.
+#[cfg(unix)]
+pub fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let device = |path: &Path| {
+        path.ancestors()
+            .find_map(|p| fs::metadata(p).ok())
+            .map(|m| m.dev())
+    };
+    match (device(a), device(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn same_filesystem(_a: &Path, _b: &Path) -> bool {
+    true
+}
+
+/// A destination file is current when it has the same size and is not older
+/// than the source.
+pub fn is_up_to_date(src: &fs::Metadata, dest: Option<&EntryStat>) -> bool {
+    match dest {
+        Some(st) => !st.is_dir && st.size == src.len() && st.mtime >= mtime_secs(src),
+        None => false,
+    }
+}
+
+/// Join a relative path onto a remote root with `/`, whatever the local OS uses.
+pub fn remote_join(root: &str, rel: &Path) -> String {
+    let mut out = root.trim_end_matches('/').to_string();
+    for c in rel.components() {
+        out.push('/');
+        out.push_str(&c.as_os_str().to_string_lossy());
+    }
+    if out.is_empty() {
+        out.push('/');
+    }
+    out
+}
+
+/// Attempts after the first for transient failures of remote requests.
+const RETRIES: u32 = 4;
+
+/// A non-success HTTP status from an HTTP based destination.
+#[derive(Debug, thiserror::Error)]
+#[error("HTTP {code}: {detail}")]
+pub struct HttpError {
+    pub code: u16,
+    pub detail: String,
+}
+
+pub fn is_http_status(e: &anyhow::Error, code: u16) -> bool {
+    e.downcast_ref::<HttpError>()
+        .is_some_and(|h| h.code == code)
+}
+
+/// Run `f`, retrying transport errors and 5xx/429 responses with exponential
+/// backoff. Client errors are returned immediately.
+pub fn with_retries<T>(mut f: impl FnMut() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt < RETRIES && is_transient(&e) => {
+                attempt += 1;
+                thread::sleep(Duration::from_secs(1 << attempt));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn is_transient(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<HttpError>() {
+        Some(h) => h.code >= 500 || h.code == 429,
+        None => true,
+    }
+}
@@ -0,0 +1,425 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use filetime::FileTime;
+
+use super::{CopyMethod, Destination, EntryStat, mtime_secs, partial_path};
+use crate::throttle::{Limiter, Throttled};
+
+pub struct LocalDestination {
+    root: PathBuf,
+    /// Cleared after the filesystem refuses a reflink, so the rest of the run
+    /// goes straight to a normal copy.
+    try_reflink: bool,
+    limiter: Option<Limiter>,
+}
+
+impl LocalDestination {
+    pub fn open(root: &Path) -> anyhow::Result<Self> {
+        if !root.exists() {
+            fs::create_dir_all(root).context("Failed to create destination")?;
+        }
+        Ok(Self {
+            root: root.to_path_buf(),
+            try_reflink: cfg!(target_os = "linux"),
+            limiter: None,
+        })
+    }
+}
+
+impl Destination for LocalDestination {
+    fn describe(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    fn display_path(&self, rel: &Path) -> String {
+        self.root.join(rel).display().to_string()
+    }
+
+    fn create_dir_all(&mut self, rel: &Path) -> anyhow::Result<()> {
+        fs::create_dir_all(self.root.join(rel))?;
+        Ok(())
+    }
+
+    fn stat(&mut self, rel: &Path) -> anyhow::Result<Option<EntryStat>> {
+        match fs::metadata(self.root.join(rel)) {
+            Ok(m) => Ok(Some(EntryStat {
+                size: m.len(),
+                mtime: mtime_secs(&m),
+                is_dir: m.is_dir(),
+            })),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put_file(&mut self, src: &Path, rel: &Path) -> anyhow::Result<CopyMethod> {
+        // Left over from a run that was killed mid-copy
+        let _ = fs::remove_file(self.root.join(partial_path(rel)));
+        self.write_partial(src, rel, 0)
+    }
+
+    fn partial_len(&mut self, rel: &Path) -> anyhow::Result<Option<u64>> {
+        match fs::metadata(self.root.join(partial_path(rel))) {
+            Ok(m) => Ok(Some(m.len())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn resume_file(&mut self, src: &Path, rel: &Path, offset: u64) -> anyhow::Result<CopyMethod> {
+        make_writable(&self.root.join(partial_path(rel)))?;
+        self.write_partial(src, rel, offset)
+    }
+
+    fn hard_link(&mut self, existing: &Path, rel: &Path) -> anyhow::Result<bool> {
+        let target = self.root.join(existing);
+        let path = self.root.join(rel);
+        if let Ok(current) = fs::symlink_metadata(&path) {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                let first = fs::metadata(&target)?;
+                if current.dev() == first.dev() && current.ino() == first.ino() {
+                    return Ok(true);
+                }
+            }
+            #[cfg(not(unix))]
+            let _ = current;
+            fs::remove_file(&path)?;
+        }
+        fs::hard_link(&target, &path)?;
+        Ok(true)
+    }
+
+    fn list_dir(&mut self, rel: &Path) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.root.join(rel))? {
+            names.push(entry?.file_name().to_string_lossy().to_string());
+        }
+        Ok(names)
+    }
+
+    fn remove(&mut self, rel: &Path) -> anyhow::Result<()> {
+        let path = self.root.join(rel);
+        if fs::symlink_metadata(&path)?.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    fn set_metadata(&mut self, src: &Path, meta: &fs::Metadata, rel: &Path) -> anyhow::Result<()> {
+        let path = self.root.join(rel);
+        let is_link = meta.file_type().is_symlink();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+            // Ownership only sticks when running as root; otherwise keep ours.
+            match std::os::unix::fs::lchown(&path, Some(meta.uid()), Some(meta.gid())) {
+                Err(e) if e.kind() != ErrorKind::PermissionDenied => return Err(e.into()),
+                _ => {}
+            }
+            if !is_link {
+                let mut mode = meta.permissions().mode();
+                if meta.is_dir() {
+                    // Keep backed up folders writable so later runs can update them.
+                    mode |= 0o200;
+                }
+                fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+            }
+            copy_xattrs(src, &path)?;
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = src;
+            if !is_link && !meta.is_dir() {
+                fs::set_permissions(&path, meta.permissions())?;
+            }
+        }
+
+        let atime = FileTime::from_last_access_time(meta);
+        let mtime = FileTime::from_last_modification_time(meta);
+        if is_link {
+            filetime::set_symlink_file_times(&path, atime, mtime)?;
+        } else {
+            filetime::set_file_times(&path, atime, mtime)?;
+        }
+        Ok(())
+    }
+
+    fn set_limiter(&mut self, limiter: Limiter) {
+        self.limiter = Some(limiter);
+    }
+
+    fn supports_symlinks(&self) -> bool {
+        cfg!(unix)
+    }
+
+    #[cfg(unix)]
+    fn put_symlink(&mut self, target: &Path, rel: &Path) -> anyhow::Result<()> {
+        let path = self.root.join(rel);
+        match fs::symlink_metadata(&path) {
+            Ok(m) if m.is_dir() => fs::remove_dir_all(&path)?,
+            Ok(_) => fs::remove_file(&path)?,
+            Err(_) => {}
+        }
+        std::os::unix::fs::symlink(target, &path)?;
+        Ok(())
+    }
+}
+
+impl LocalDestination {
+    /// Write `src` from `start` on into the partial file of `rel`, then move
+    /// it into place. The rename replaces links left by an earlier run
+    /// instead of writing through them.
+    fn write_partial(&mut self, src: &Path, rel: &Path, start: u64) -> anyhow::Result<CopyMethod> {
+        let path = self.root.join(rel);
+        let partial = self.root.join(partial_path(rel));
+        match self
+            .write_data(src, &partial, start)
+            .and_then(|method| replace(&partial, &path).map(|()| method))
+        {
+            Ok(method) => Ok(method),
+            Err(e) => {
+                let _ = fs::remove_file(&partial);
+                Err(e.into())
+            }
+        }
+    }
+
+    fn write_data(&mut self, src: &Path, dest: &Path, start: u64) -> io::Result<CopyMethod> {
+        if self.try_reflink && start == 0 {
+            match reflink(src, dest) {
+                Ok(()) => return Ok(CopyMethod::Reflink),
+                Err(e) if is_reflink_unsupported(&e) => self.try_reflink = false,
+                // e.g. out of space for the new inode; a plain copy reports it
+                Err(_) => {}
+            }
+        }
+        copy_data(src, dest, start, self.limiter.as_ref())
+    }
+}
+
+/// Copies killed mid-way keep the source mode, which may be read-only.
+fn make_writable(path: &Path) -> io::Result<()> {
+    let mut perms = fs::metadata(path)?.permissions();
+    if perms.readonly() {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            perms.set_mode(perms.mode() | 0o200);
+        }
+        #[cfg(not(unix))]
+        #[allow(clippy::permissions_set_readonly_false)]
+        perms.set_readonly(false);
+        fs::set_permissions(path, perms)?;
+    }
+    Ok(())
+}
+
+/// Move a finished `.partial` file over `path`.
+fn replace(partial: &Path, path: &Path) -> io::Result<()> {
+    // Windows refuses to rename over read-only copies whose source mode was
+    // preserved.
+    #[cfg(windows)]
+    if let Ok(existing) = fs::symlink_metadata(path)
+        && existing.permissions().readonly()
+    {
+        fs::remove_file(path)?;
+    }
+    fs::rename(partial, path)
+}
+
+/// Copy file contents, keeping holes in sparse files (VM images, databases)
+/// instead of writing them out as zeros.
+#[cfg(target_os = "linux")]
+fn copy_data(
+    src: &Path,
+    dest: &Path,
+    start: u64,
+    limiter: Option<&Limiter>,
+) -> io::Result<CopyMethod> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = fs::metadata(src)?;
+    if meta.blocks() * 512 >= meta.len() {
+        copy_from(src, dest, start, limiter)?;
+        return Ok(CopyMethod::Full);
+    }
+    copy_sparse(src, dest, meta.len(), start, limiter)?;
+    Ok(CopyMethod::Sparse)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_data(
+    src: &Path,
+    dest: &Path,
+    start: u64,
+    limiter: Option<&Limiter>,
+) -> io::Result<CopyMethod> {
+    copy_from(src, dest, start, limiter)?;
+    Ok(CopyMethod::Full)
+}
+
+/// Plain copy. A non-zero `start` continues the partial file `dest` instead
+/// of starting over.
+fn copy_from(src: &Path, dest: &Path, start: u64, limiter: Option<&Limiter>) -> io::Result<()> {
+    use std::fs::{File, OpenOptions};
+    use std::io::{Seek, SeekFrom};
+
+    // The kernel copy is fastest but cannot be throttled.
+    if start == 0 && limiter.is_none() {
+        fs::copy(src, dest)?;
+        return Ok(());
+    }
+    let mut input = File::open(src)?;
+    input.seek(SeekFrom::Start(start))?;
+    let mut output = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(start == 0)
+        .open(dest)?;
+    output.set_len(start)?;
+    output.seek(SeekFrom::Start(start))?;
+    io::copy(&mut Throttled::new(input, limiter), &mut output)?;
+    Ok(())
+}
+
+/// Clone `src` into `dest` with `FICLONE`. Only works within one filesystem
+/// that supports shared extents.
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dest: &Path) -> io::Result<()> {
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+
+    let input = File::open(src)?;
+    let output = File::create(dest)?;
+    // SAFETY: both descriptors stay open for the duration of the call.
+    let rc = unsafe { libc::ioctl(output.as_raw_fd(), libc::FICLONE, input.as_raw_fd()) };
+    if rc != 0 {
+        let err = io::Error::last_os_error();
+        drop(output);
+        let _ = fs::remove_file(dest);
+        return Err(err);
+    }
+    fs::set_permissions(dest, input.metadata()?.permissions())?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &Path, _dest: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Errors meaning reflinks cannot work here at all: another filesystem, or
+/// one without shared extents.
+fn is_reflink_unsupported(e: &io::Error) -> bool {
+    #[cfg(target_os = "linux")]
+    if let Some(code) = e.raw_os_error() {
+        return matches!(
+            code,
+            libc::EXDEV | libc::EOPNOTSUPP | libc::EINVAL | libc::ENOTTY | libc::ENOSYS
+        );
+    }
+    e.kind() == ErrorKind::Unsupported
+}
+
+#[cfg(target_os = "linux")]
+fn copy_sparse(
+    src: &Path,
+    dest: &Path,
+    len: u64,
+    start: u64,
+    limiter: Option<&Limiter>,
+) -> io::Result<()> {
+    use std::fs::{File, OpenOptions};
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::os::fd::AsRawFd;
+
+    let mut input = File::open(src)?;
+    let mut output = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(start == 0)
+        .open(dest)?;
+    // Data is written in order, so the length of an interrupted copy is
+    // where to resume. Holes before it read back as zeros either way.
+    output.set_len(start)?;
+
+    let fd = input.as_raw_fd();
+    let mut offset = start as i64;
+    let mut buf = vec![0u8; 1024 * 1024];
+    while (offset as u64) < len {
+        // SAFETY: plain lseek(2) on a descriptor owned by `input`.
+        let data = unsafe { libc::lseek(fd, offset, libc::SEEK_DATA) };
+        if data < 0 {
+            let err = io::Error::last_os_error();
+            // ENXIO: no data after `offset`, the rest is a hole.
+            if err.raw_os_error() == Some(libc::ENXIO) {
+                break;
+            }
+            return Err(err);
+        }
+        // SAFETY: as above.
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        input.seek(SeekFrom::Start(data as u64))?;
+        output.seek(SeekFrom::Start(data as u64))?;
+        let mut remaining = (hole - data) as u64;
+        while remaining > 0 {
+            let want = remaining.min(buf.len() as u64) as usize;
+            let n = input.read(&mut buf[..want])?;
+            if n == 0 {
+                break;
+            }
+            output.write_all(&buf[..n])?;
+            if let Some(limiter) = limiter {
+                limiter.consume(n as u64);
+            }
+            remaining -= n as u64;
+        }
+        offset = hole;
+    }
+    // Unwritten ranges stay holes once the length is set.
+    output.set_len(len)?;
+    fs::set_permissions(dest, fs::metadata(src)?.permissions())?;
+    Ok(())
+}
+
+/// Copy extended attributes without following links. Filesystems without
+/// xattr support and namespaces we may not write (e.g. `security.*` as a
+/// normal user) are skipped.
+#[cfg(unix)]
+fn copy_xattrs(src: &Path, dest: &Path) -> io::Result<()> {
+    let names = match xattr::list(src) {
+        Ok(names) => names,
+        Err(e) if is_xattr_unsupported(&e) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for name in names {
+        let Some(value) = xattr::get(src, &name)? else {
+            continue;
+        };
+        match xattr::set(dest, &name, &value) {
+            Err(e) if !is_xattr_unsupported(&e) && e.kind() != ErrorKind::PermissionDenied => {
+                return Err(e);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_xattr_unsupported(e: &io::Error) -> bool {
+    e.kind() == ErrorKind::Unsupported || e.raw_os_error() == Some(libc::ENOTSUP)
+}
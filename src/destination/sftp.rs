@@ -0,0 +1,292 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use std::net::TcpStream;
+use std::path::Path;
+
+use anyhow::{Context, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use ssh2::{
+    CheckResult, ErrorCode, FileStat, KnownHostFileKind, OpenFlags, OpenType, Session, Sftp,
+};
+
+use super::{
+    CopyMethod, Destination, EntryStat, atime_secs, mtime_secs, partial_path, remote_join,
+};
+use crate::throttle::{Limiter, Throttled};
+
+// LIBSSH2_FX_NO_SUCH_FILE
+const SFTP_NO_SUCH_FILE: i32 = 2;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SftpConfig {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    /// Private key file; empty means authenticate through the ssh-agent.
+    pub key_file: String,
+    pub remote_path: String,
+}
+
+impl Default for SftpConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 22,
+            user: String::new(),
+            key_file: String::new(),
+            remote_path: String::new(),
+        }
+    }
+}
+
+impl SftpConfig {
+    pub fn describe(&self) -> String {
+        format!(
+            "sftp://{}@{}:{}{}",
+            self.user, self.host, self.port, self.remote_path
+        )
+    }
+}
+
+pub struct SftpDestination {
+    // Keeps the connection alive for `sftp`.
+    _session: Session,
+    sftp: Sftp,
+    config: SftpConfig,
+    limiter: Option<Limiter>,
+}
+
+impl SftpDestination {
+    pub fn connect(config: &SftpConfig) -> anyhow::Result<Self> {
+        if config.host.trim().is_empty() || config.user.trim().is_empty() {
+            bail!("SFTP host and user are required");
+        }
+        let tcp = TcpStream::connect((config.host.as_str(), config.port))
+            .with_context(|| format!("Failed to connect to {}:{}", config.host, config.port))?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+        verify_host_key(&session, &config.host, config.port)?;
+
+        if config.key_file.trim().is_empty() {
+            session.userauth_agent(&config.user)?;
+        } else {
+            session.userauth_pubkey_file(&config.user, None, Path::new(&config.key_file), None)?;
+        }
+        if !session.authenticated() {
+            bail!("SFTP authentication failed for {}", config.user);
+        }
+
+        let sftp = session.sftp()?;
+        let mut dest = Self {
+            _session: session,
+            sftp,
+            config: config.clone(),
+            limiter: None,
+        };
+        dest.create_dir_all(Path::new(""))?;
+        Ok(dest)
+    }
+
+    fn remote(&self, rel: &Path) -> String {
+        remote_join(&self.config.remote_path, rel)
+    }
+
+    /// Upload `src` from `start` on into the partial file of `rel`, then
+    /// rename it into place.
+    fn write_partial(&mut self, src: &Path, rel: &Path, start: u64) -> anyhow::Result<CopyMethod> {
+        let path = self.remote(rel);
+        let partial = self.remote(&partial_path(rel));
+        let written = File::open(src)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut input| {
+                let mut remote = if start == 0 {
+                    self.sftp.create(Path::new(&partial))?
+                } else {
+                    let mut file = self.sftp.open_mode(
+                        Path::new(&partial),
+                        OpenFlags::WRITE,
+                        0o644,
+                        OpenType::File,
+                    )?;
+                    file.setstat(FileStat {
+                        size: Some(start),
+                        uid: None,
+                        gid: None,
+                        perm: None,
+                        atime: None,
+                        mtime: None,
+                    })?;
+                    file.seek(SeekFrom::Start(start))?;
+                    input.seek(SeekFrom::Start(start))?;
+                    file
+                };
+                io::copy(
+                    &mut Throttled::new(input, self.limiter.as_ref()),
+                    &mut remote,
+                )?;
+                Ok(())
+            });
+        if let Err(e) = written {
+            let _ = self.sftp.unlink(Path::new(&partial));
+            return Err(e);
+        }
+        // SFTP v3 servers such as OpenSSH will not rename onto an existing file.
+        if self
+            .sftp
+            .rename(Path::new(&partial), Path::new(&path), None)
+            .is_err()
+        {
+            let _ = self.sftp.unlink(Path::new(&path));
+            self.sftp
+                .rename(Path::new(&partial), Path::new(&path), None)?;
+        }
+        Ok(CopyMethod::Full)
+    }
+}
+
+fn verify_host_key(session: &Session, host: &str, port: u16) -> anyhow::Result<()> {
+    let known_hosts = dirs_next::home_dir()
+        .map(|h| h.join(".ssh").join("known_hosts"))
+        .ok_or_else(|| anyhow!("Cannot locate ~/.ssh/known_hosts"))?;
+    let mut kh = session.known_hosts()?;
+    kh.read_file(&known_hosts, KnownHostFileKind::OpenSSH)
+        .with_context(|| format!("Failed to read {}", known_hosts.display()))?;
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| anyhow!("Server sent no host key"))?;
+    match kh.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => bail!("Host key for {host} does not match known_hosts"),
+        CheckResult::NotFound => {
+            bail!("{host} is not in known_hosts; connect once with ssh to trust it")
+        }
+        CheckResult::Failure => bail!("Failed to check host key for {host}"),
+    }
+}
+
+fn is_not_found(e: &ssh2::Error) -> bool {
+    e.code() == ErrorCode::SFTP(SFTP_NO_SUCH_FILE)
+}
+
+impl Destination for SftpDestination {
+    fn describe(&self) -> String {
+        self.config.describe()
+    }
+
+    fn display_path(&self, rel: &Path) -> String {
+        format!(
+            "sftp://{}@{}:{}{}",
+            self.config.user,
+            self.config.host,
+            self.config.port,
+            self.remote(rel)
+        )
+    }
+
+    fn create_dir_all(&mut self, rel: &Path) -> anyhow::Result<()> {
+        let full = self.remote(rel);
+        // A relative remote path is under the login directory, like put_file.
+        let mut current = String::new();
+        for part in full.split('/').filter(|p| !p.is_empty()) {
+            if !current.is_empty() || full.starts_with('/') {
+                current.push('/');
+            }
+            current.push_str(part);
+            let path = Path::new(&current);
+            match self.sftp.stat(path) {
+                Ok(st) if st.is_dir() => continue,
+                Ok(_) => bail!("{current} exists and is not a directory"),
+                Err(e) if is_not_found(&e) => {
+                    self.sftp
+                        .mkdir(path, 0o755)
+                        .with_context(|| format!("Failed to create {current}"))?;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn stat(&mut self, rel: &Path) -> anyhow::Result<Option<EntryStat>> {
+        match self.sftp.stat(Path::new(&self.remote(rel))) {
+            Ok(st) => Ok(Some(EntryStat {
+                size: st.size.unwrap_or(0),
+                mtime: st.mtime.unwrap_or(0) as i64,
+                is_dir: st.is_dir(),
+            })),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put_file(&mut self, src: &Path, rel: &Path) -> anyhow::Result<CopyMethod> {
+        self.write_partial(src, rel, 0)
+    }
+
+    fn partial_len(&mut self, rel: &Path) -> anyhow::Result<Option<u64>> {
+        match self.sftp.stat(Path::new(&self.remote(&partial_path(rel)))) {
+            Ok(st) => Ok(st.size),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn resume_file(&mut self, src: &Path, rel: &Path, offset: u64) -> anyhow::Result<CopyMethod> {
+        self.write_partial(src, rel, offset)
+    }
+
+    fn set_limiter(&mut self, limiter: Limiter) {
+        self.limiter = Some(limiter);
+    }
+
+    fn list_dir(&mut self, rel: &Path) -> anyhow::Result<Vec<String>> {
+        let entries = self.sftp.readdir(Path::new(&self.remote(rel)))?;
+        Ok(entries
+            .iter()
+            .filter_map(|(p, _)| p.file_name())
+            .map(|n| n.to_string_lossy().to_string())
+            .filter(|n| n != "." && n != "..")
+            .collect())
+    }
+
+    fn remove(&mut self, rel: &Path) -> anyhow::Result<()> {
+        let path = self.remote(rel);
+        let st = self.sftp.lstat(Path::new(&path))?;
+        if st.is_dir() {
+            for name in self.list_dir(rel)? {
+                self.remove(&rel.join(name))?;
+            }
+            self.sftp.rmdir(Path::new(&path))?;
+        } else {
+            self.sftp.unlink(Path::new(&path))?;
+        }
+        Ok(())
+    }
+
+    fn set_metadata(
+        &mut self,
+        _src: &Path,
+        meta: &std::fs::Metadata,
+        rel: &Path,
+    ) -> anyhow::Result<()> {
+        #[cfg(unix)]
+        let perm = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(meta.permissions().mode() | if meta.is_dir() { 0o200 } else { 0 })
+        };
+        #[cfg(not(unix))]
+        let perm = None;
+        let stat = FileStat {
+            size: None,
+            uid: None,
+            gid: None,
+            perm,
+            atime: Some(atime_secs(meta) as u64),
+            mtime: Some(mtime_secs(meta) as u64),
+        };
+        self.sftp.setstat(Path::new(&self.remote(rel)), stat)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::bail;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::DateTime;
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, utf8_percent_encode};
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    CopyMethod, Destination, EntryStat, HttpError, is_http_status, mtime_secs, with_retries,
+};
+use crate::throttle::{Limiter, Throttled};
+
+/// Characters escaped in a single path segment.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:getcontentlength/><d:getlastmodified/><d:resourcetype/></d:prop></d:propfind>"#;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebDavConfig {
+    /// Collection URL, e.g. `https://cloud.example.com/remote.php/dav/files/me/Backups`
+    pub url: String,
+    pub user: String,
+    pub password: String,
+}
+
+impl WebDavConfig {
+    pub fn describe(&self) -> String {
+        self.url.clone()
+    }
+}
+
+/// One `<response>` of a PROPFIND multistatus.
+#[derive(Default)]
+struct PropEntry {
+    href: String,
+    size: u64,
+    mtime: i64,
+    is_dir: bool,
+}
+
+pub struct WebDavDestination {
+    agent: ureq::Agent,
+    config: WebDavConfig,
+    auth: Option<String>,
+    limiter: Option<Limiter>,
+}
+
+impl WebDavDestination {
+    pub fn connect(config: &WebDavConfig) -> anyhow::Result<Self> {
+        if config.url.trim().is_empty() {
+            bail!("WebDAV URL is required");
+        }
+        let auth = (!config.user.is_empty()).then(|| {
+            let pair = format!("{}:{}", config.user, config.password);
+            format!("Basic {}", STANDARD.encode(pair))
+        });
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(15))
+            .timeout_read(Duration::from_secs(120))
+            .build();
+        let mut dest = Self {
+            agent,
+            config: config.clone(),
+            auth,
+            limiter: None,
+        };
+        dest.create_dir_all(Path::new(""))?;
+        Ok(dest)
+    }
+
+    fn url(&self, rel: &Path) -> String {
+        let mut url = self.config.url.trim_end_matches('/').to_owned();
+        for c in rel.components() {
+            url.push('/');
+            url.extend(utf8_percent_encode(
+                &c.as_os_str().to_string_lossy(),
+                SEGMENT,
+            ));
+        }
+        url
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let req = self.agent.request(method, url);
+        match &self.auth {
+            Some(auth) => req.set("Authorization", auth),
+            None => req,
+        }
+    }
+
+    fn propfind(&self, rel: &Path, depth: &str) -> anyhow::Result<Vec<PropEntry>> {
+        let url = self.url(rel);
+        let body = with_retries(|| {
+            let resp = self
+                .request("PROPFIND", &url)
+                .set("Depth", depth)
+                .set("Content-Type", "application/xml; charset=utf-8")
+                .send_string(PROPFIND_BODY);
+            Ok(check(resp)?.into_string()?)
+        })?;
+        parse_multistatus(&body)
+    }
+}
+
+fn check(resp: Result<ureq::Response, ureq::Error>) -> anyhow::Result<ureq::Response> {
+    match resp {
+        Ok(resp) => Ok(resp),
+        Err(ureq::Error::Status(code, resp)) => {
+            let detail = resp.status_text().to_owned();
+            Err(HttpError { code, detail }.into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Servers pick their own namespace prefixes (`d:`, `D:`, `lp1:`), so elements
+/// are matched on their local name only.
+fn parse_multistatus(body: &str) -> anyhow::Result<Vec<PropEntry>> {
+    let mut reader = Reader::from_str(body);
+    let mut entries = Vec::new();
+    let mut current: Option<PropEntry> = None;
+    let mut element = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                element = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                match element.as_str() {
+                    "response" => current = Some(PropEntry::default()),
+                    "collection" => {
+                        if let Some(entry) = current.as_mut() {
+                            entry.is_dir = true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Empty(e) => {
+                if e.local_name().as_ref() == b"collection"
+                    && let Some(entry) = current.as_mut()
+                {
+                    entry.is_dir = true;
+                }
+            }
+            Event::Text(t) => {
+                let Some(entry) = current.as_mut() else {
+                    continue;
+                };
+                let text = t.unescape()?.trim().to_string();
+                match element.as_str() {
+                    "href" => entry.href = text,
+                    "getcontentlength" => entry.size = text.parse().unwrap_or(0),
+                    "getlastmodified" => {
+                        entry.mtime = DateTime::parse_from_rfc2822(&text)
+                            .map(|d| d.timestamp())
+                            .unwrap_or(0)
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(e) => {
+                if e.local_name().as_ref() == b"response"
+                    && let Some(entry) = current.take()
+                {
+                    entries.push(entry);
+                }
+                element.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+fn decoded_path(href: &str) -> String {
+    // hrefs may be absolute URLs or absolute paths
+    let path = match href.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or("/"),
+        None => href,
+    };
+    percent_decode_str(path)
+        .decode_utf8_lossy()
+        .trim_end_matches('/')
+        .to_string()
+}
+
+impl Destination for WebDavDestination {
+    fn describe(&self) -> String {
+        self.config.describe()
+    }
+
+    fn display_path(&self, rel: &Path) -> String {
+        self.url(rel)
+    }
+
+    fn create_dir_all(&mut self, rel: &Path) -> anyhow::Result<()> {
+        if self.stat(rel)?.is_some_and(|st| st.is_dir) {
+            return Ok(());
+        }
+        let mut current = std::path::PathBuf::new();
+        let mut parts = vec![current.clone()];
+        for c in rel.components() {
+            current.push(c);
+            parts.push(current.clone());
+        }
+        for part in parts {
+            if self.stat(&part)?.is_some_and(|st| st.is_dir) {
+                continue;
+            }
+            let url = self.url(&part);
+            match with_retries(|| check(self.request("MKCOL", &url).call())) {
+                Ok(_) => {}
+                // 405: the collection already exists
+                Err(e) if is_http_status(&e, 405) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn stat(&mut self, rel: &Path) -> anyhow::Result<Option<EntryStat>> {
+        match self.propfind(rel, "0") {
+            Ok(entries) => Ok(entries.into_iter().next().map(|e| EntryStat {
+                size: e.size,
+                mtime: e.mtime,
+                is_dir: e.is_dir,
+            })),
+            Err(e) if is_http_status(&e, 404) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn put_file(&mut self, src: &Path, rel: &Path) -> anyhow::Result<CopyMethod> {
+        let url = self.url(rel);
+        let mtime = mtime_secs(&src.metadata()?);
+        with_retries(|| {
+            let file = File::open(src)?;
+            // Nextcloud/ownCloud keep the source mtime when told; others ignore it.
+            check(
+                self.request("PUT", &url)
+                    .set("X-OC-Mtime", &mtime.to_string())
+                    .send(Throttled::new(file, self.limiter.as_ref())),
+            )
+        })?;
+        Ok(CopyMethod::Full)
+    }
+
+    fn set_limiter(&mut self, limiter: Limiter) {
+        self.limiter = Some(limiter);
+    }
+
+    fn list_dir(&mut self, rel: &Path) -> anyhow::Result<Vec<String>> {
+        let own = decoded_path(&self.url(rel));
+        let entries = match self.propfind(rel, "1") {
+            Ok(entries) => entries,
+            Err(e) if is_http_status(&e, 404) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        Ok(entries
+            .iter()
+            .map(|e| decoded_path(&e.href))
+            .filter(|p| !own.ends_with(p.as_str()))
+            .filter_map(|p| p.rsplit('/').next().map(str::to_owned))
+            .collect())
+    }
+
+    fn remove(&mut self, rel: &Path) -> anyhow::Result<()> {
+        let url = self.url(rel);
+        match with_retries(|| check(self.request("DELETE", &url).call())) {
+            Ok(_) => Ok(()),
+            Err(e) if is_http_status(&e, 404) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
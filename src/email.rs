@@ -0,0 +1,359 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeDelta, Timelike};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+
+use crate::config_dir;
+use crate::report::Outcome;
+
+/// How long a failed digest waits before it is mailed again.
+const DIGEST_RETRY: TimeDelta = TimeDelta::minutes(15);
+/// Runs a digest lists at most; older ones are only counted, so a digest that
+/// cannot be sent does not grow without bound.
+const MAX_DIGEST_ENTRIES: usize = 500;
+
+/// How the SMTP connection is secured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Security {
+    /// Plain text, e.g. a local relay or a test sink.
+    None,
+    #[default]
+    StartTls,
+    /// TLS from the first byte, usually port 465.
+    Tls,
+}
+
+impl Security {
+    pub const ALL: [Security; 3] = [Security::None, Security::StartTls, Security::Tls];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Security::None => "No encryption",
+            Security::StartTls => "STARTTLS",
+            Security::Tls => "TLS",
+        }
+    }
+}
+
+/// SMTP server and what to mail, shared by all schedules.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailSettings {
+    pub host: String,
+    pub port: u16,
+    pub security: Security,
+    /// Empty to send without authentication.
+    pub user: String,
+    pub password: String,
+    pub from: String,
+    /// Comma-separated addresses.
+    pub to: String,
+    /// Mail each failed full backup as soon as it ends.
+    pub alert_on_failure: bool,
+    /// Mail a summary of all results once a day.
+    pub daily_digest: bool,
+    /// Local hour the digest goes out at.
+    pub digest_hour: u32,
+}
+
+impl Default for EmailSettings {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 587,
+            security: Security::default(),
+            user: String::new(),
+            password: String::new(),
+            from: String::new(),
+            to: String::new(),
+            alert_on_failure: false,
+            daily_digest: false,
+            digest_hour: 8,
+        }
+    }
+}
+
+impl EmailSettings {
+    pub fn is_configured(&self) -> bool {
+        !self.host.trim().is_empty() && !self.from.trim().is_empty() && !self.to.trim().is_empty()
+    }
+
+    /// Send one plain-text mail to every recipient.
+    pub fn send(&self, subject: &str, body: &str) -> anyhow::Result<()> {
+        let mut builder = Message::builder()
+            .from(self.from.trim().parse()?)
+            .subject(subject);
+        for to in self.to.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            builder = builder.to(to.parse()?);
+        }
+        let message = builder.body(body.to_owned())?;
+
+        let host = self.host.trim();
+        let mut transport = match self.security {
+            Security::None => SmtpTransport::builder_dangerous(host),
+            Security::StartTls => SmtpTransport::starttls_relay(host)?,
+            Security::Tls => SmtpTransport::relay(host)?,
+        }
+        .port(self.port)
+        .timeout(Some(Duration::from_secs(30)));
+        if !self.user.trim().is_empty() {
+            transport = transport.credentials(Credentials::new(
+                self.user.trim().to_owned(),
+                self.password.clone(),
+            ));
+        }
+        transport.build().send(&message)?;
+        Ok(())
+    }
+}
+
+/// One finished run waiting for the next digest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DigestEntry {
+    pub time: String,
+    pub schedule: String,
+    pub result: String,
+}
+
+/// The entries a digest mail covers, so they are only dropped once it went
+/// out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DigestBatch {
+    pub entries: usize,
+    pub day: NaiveDate,
+}
+
+/// Results collected since the last digest, kept in the config folder so a
+/// restart does not lose them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Digest {
+    last_sent: Option<NaiveDate>,
+    entries: Vec<DigestEntry>,
+    /// Runs left out of `entries` to stay within `MAX_DIGEST_ENTRIES`.
+    dropped: usize,
+    /// A digest is being mailed; no second one starts meanwhile.
+    #[serde(skip)]
+    sending: bool,
+    /// After a failed send, when to try again.
+    #[serde(skip)]
+    retry_at: Option<NaiveDateTime>,
+    /// Where the digest is kept; `None` keeps it in memory only.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Digest {
+    pub fn load() -> Self {
+        let path = digest_path();
+        let loaded: Option<Self> = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|text| serde_json::from_str(&text).ok());
+        Self {
+            path,
+            ..loaded.unwrap_or_default()
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // Written aside and renamed so a crash mid-save keeps the old digest.
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, schedule: String, outcome: Outcome) -> anyhow::Result<()> {
+        let result = match outcome {
+            Outcome::Success => "success".to_owned(),
+            Outcome::Partial { failed } => format!("finished, {failed} file(s) failed"),
+            Outcome::Failed => "FAILED".to_owned(),
+        };
+        self.entries.push(DigestEntry {
+            time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            schedule,
+            result,
+        });
+        // Not while sending, which would shift the entries of its batch.
+        if !self.sending && self.entries.len() > MAX_DIGEST_ENTRIES {
+            let excess = self.entries.len() - MAX_DIGEST_ENTRIES;
+            self.entries.drain(..excess);
+            self.dropped += excess;
+        }
+        self.save()
+    }
+
+    /// The digest to mail if the one for `now`'s day is due at `hour`, as
+    /// subject, body and the batch to pass to `sent` once it went out. The
+    /// entries stay until then.
+    pub fn take_due(
+        &mut self,
+        hour: u32,
+        now: NaiveDateTime,
+    ) -> anyhow::Result<Option<(String, String, DigestBatch)>> {
+        let today = now.date();
+        let Some(last_sent) = self.last_sent else {
+            // Just enabled: the first digest goes out tomorrow.
+            self.last_sent = Some(today);
+            self.save()?;
+            return Ok(None);
+        };
+        if self.sending
+            || now.hour() < hour
+            || last_sent >= today
+            || self.retry_at.is_some_and(|at| now < at)
+        {
+            return Ok(None);
+        }
+        self.sending = true;
+
+        let entries = &self.entries;
+        let failed = entries.iter().filter(|e| e.result != "success").count();
+        let subject = if failed == 0 {
+            format!(
+                "AutoBackup daily summary: {} run(s), all fine",
+                entries.len()
+            )
+        } else {
+            format!(
+                "AutoBackup daily summary: {failed} of {} run(s) had problems",
+                entries.len()
+            )
+        };
+        let mut body = String::new();
+        if self.dropped > 0 {
+            let _ = writeln!(
+                body,
+                "{} older run(s) are not listed; the summary could not be mailed for a while.",
+                self.dropped
+            );
+        }
+        if entries.is_empty() && self.dropped == 0 {
+            body.push_str("No backups ran since the last summary.\n");
+        }
+        for e in entries {
+            let _ = writeln!(body, "{}  {}  {}", e.time, e.schedule, e.result);
+        }
+        let batch = DigestBatch {
+            entries: entries.len(),
+            day: today,
+        };
+        Ok(Some((subject, body, batch)))
+    }
+
+    /// The digest for `batch` went out: drop its entries, keeping any
+    /// recorded while it was being sent.
+    pub fn sent(&mut self, batch: DigestBatch) -> anyhow::Result<()> {
+        self.sending = false;
+        self.retry_at = None;
+        self.entries.drain(..batch.entries.min(self.entries.len()));
+        self.dropped = 0;
+        self.last_sent = Some(batch.day);
+        self.save()
+    }
+
+    /// Mailing the digest failed at `now`; keep its entries and try again a
+    /// little later.
+    pub fn send_failed(&mut self, now: NaiveDateTime) {
+        self.sending = false;
+        self.retry_at = Some(now + DIGEST_RETRY);
+    }
+}
+
+fn digest_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("email_digest.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 5, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn digest(last_sent: u32, results: &[&str]) -> Digest {
+        Digest {
+            last_sent: Some(at(last_sent, 0).date()),
+            entries: results
+                .iter()
+                .map(|result| DigestEntry {
+                    time: "2024-05-01 12:00:00".to_owned(),
+                    schedule: "Documents".to_owned(),
+                    result: (*result).to_owned(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn due_once_a_day_from_the_hour() {
+        let mut d = digest(1, &["success", "FAILED"]);
+        assert!(d.take_due(8, at(2, 7)).unwrap().is_none());
+        let (subject, body, batch) = d.take_due(8, at(2, 8)).unwrap().unwrap();
+        assert!(subject.contains("1 of 2 run(s) had problems"));
+        assert_eq!(body.lines().count(), 2);
+        assert_eq!(
+            batch,
+            DigestBatch {
+                entries: 2,
+                day: at(2, 0).date()
+            }
+        );
+        // Not again while it is being sent.
+        assert!(d.take_due(8, at(2, 9)).unwrap().is_none());
+    }
+
+    #[test]
+    fn sent_keeps_entries_recorded_meanwhile() {
+        let mut d = digest(1, &["success"]);
+        let (_, _, batch) = d.take_due(8, at(2, 8)).unwrap().unwrap();
+        d.entries.push(d.entries[0].clone());
+        d.sent(batch).unwrap();
+        assert_eq!(d.entries.len(), 1);
+        assert!(d.take_due(8, at(2, 20)).unwrap().is_none());
+        assert!(d.take_due(8, at(3, 8)).unwrap().is_some());
+    }
+
+    #[test]
+    fn failed_send_retries_later_with_the_same_entries() {
+        let mut d = digest(1, &["success"]);
+        d.take_due(8, at(2, 8)).unwrap().unwrap();
+        d.send_failed(at(2, 8));
+        assert!(
+            d.take_due(8, at(2, 8) + TimeDelta::minutes(5))
+                .unwrap()
+                .is_none()
+        );
+        let (subject, _, batch) = d.take_due(8, at(2, 8) + DIGEST_RETRY).unwrap().unwrap();
+        assert!(subject.contains("1 run(s), all fine"));
+        assert_eq!(batch.entries, 1);
+    }
+
+    #[test]
+    fn entries_are_capped_while_the_digest_cannot_go_out() {
+        let mut d = digest(1, &[]);
+        for _ in 0..MAX_DIGEST_ENTRIES + 3 {
+            d.record("Documents".to_owned(), Outcome::Success).unwrap();
+        }
+        assert_eq!(d.entries.len(), MAX_DIGEST_ENTRIES);
+        let (subject, body, batch) = d.take_due(8, at(2, 8)).unwrap().unwrap();
+        assert!(subject.contains(&format!("{MAX_DIGEST_ENTRIES} run(s)")));
+        assert!(body.starts_with("3 older run(s) are not listed"));
+        d.sent(batch).unwrap();
+        assert_eq!((d.entries.len(), d.dropped), (0, 0));
+    }
+}
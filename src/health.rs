@@ -0,0 +1,263 @@
+use eframe::egui::{self, Color32, Response, Sense, Shape, Stroke, Ui};
+use serde::{Deserialize, Serialize};
+
+use crate::metrics::{RunPoint, ScheduleMetrics};
+
+/// A destination forecast to be full sooner than this is flagged.
+pub const FULL_SOON_DAYS: f64 = 14.0;
+
+/// When a schedule's last successful backup counts as stale, in multiples of
+/// its period.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthSettings {
+    pub amber_after: f32,
+    pub red_after: f32,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        Self {
+            amber_after: 1.5,
+            red_after: 3.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Health {
+    Green,
+    Amber,
+    Red,
+    /// No run recorded yet.
+    Unknown,
+}
+
+impl Health {
+    /// Judge a schedule from its recorded runs at Unix time `now`. A failed
+    /// last run makes a fresh backup amber; no success at all after a run is
+    /// red.
+    pub fn assess(
+        period_hours: i32,
+        metrics: Option<&ScheduleMetrics>,
+        settings: &HealthSettings,
+        now: i64,
+    ) -> Self {
+        let Some(m) = metrics.filter(|m| m.last_run.is_some()) else {
+            return Health::Unknown;
+        };
+        let Some(last_success) = m.last_success else {
+            return Health::Red;
+        };
+        let age_hours = (now - last_success) as f32 / 3600.0;
+        let period = period_hours.max(1) as f32;
+        if age_hours >= period * settings.red_after {
+            Health::Red
+        } else if age_hours >= period * settings.amber_after || m.last_run != m.last_success {
+            Health::Amber
+        } else {
+            Health::Green
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Health::Green => "OK",
+            Health::Amber => "Attention",
+            Health::Red => "Stale",
+            Health::Unknown => "No runs yet",
+        }
+    }
+
+    pub fn color(self) -> Color32 {
+        match self {
+            Health::Green => Color32::GREEN,
+            Health::Amber => Color32::from_rgb(255, 170, 0),
+            Health::Red => Color32::RED,
+            Health::Unknown => Color32::GRAY,
+        }
+    }
+}
+
+/// Days until the destination fills up at the rate its free space shrank
+/// over `history`, by a least-squares fit. `None` without at least three
+/// measured runs a day apart, or when free space is not shrinking.
+pub fn days_until_full(history: &[RunPoint]) -> Option<f64> {
+    let points: Vec<(f64, f64)> = history
+        .iter()
+        .filter_map(|p| {
+            p.dest_free
+                .map(|free| (p.finished as f64 / 86400.0, free as f64))
+        })
+        .collect();
+    let (&(first_day, _), &(last_day, last_free)) = (points.first()?, points.last()?);
+    if points.len() < 3 || last_day - first_day < 1.0 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_day = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_free = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let spread: f64 = points.iter().map(|p| (p.0 - mean_day).powi(2)).sum();
+    let bytes_per_day = points
+        .iter()
+        .map(|p| (p.0 - mean_day) * (p.1 - mean_free))
+        .sum::<f64>()
+        / spread;
+    (bytes_per_day < 0.0).then(|| last_free / -bytes_per_day)
+}
+
+/// A forecast in days, e.g. `about 3 days`.
+pub fn format_days(days: f64) -> String {
+    if days < 1.0 {
+        "less than a day".to_owned()
+    } else {
+        format!("about {days:.0} days")
+    }
+}
+
+/// `bytes` with a binary unit, e.g. `1.5 GiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["bytes", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} bytes")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// A duration in its largest sensible unit, e.g. `3h 20m`.
+pub fn format_duration(secs: i64) -> String {
+    let secs = secs.max(0);
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m {}s", secs / 60, secs % 60),
+        3600..86400 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d {}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+/// A small line chart of `values`, oldest first, without axes.
+pub fn sparkline(ui: &mut Ui, values: &[f64]) -> Response {
+    let (rect, response) = ui.allocate_exact_size(egui::vec2(80.0, 16.0), Sense::hover());
+    if values.len() < 2 {
+        return response;
+    }
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let span = (max - min).max(f64::EPSILON);
+    let step = rect.width() / (values.len() - 1) as f32;
+    let points = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            egui::pos2(
+                rect.left() + step * i as f32,
+                rect.bottom() - rect.height() * ((v - min) / span) as f32,
+            )
+        })
+        .collect();
+    ui.painter().add(Shape::line(
+        points,
+        Stroke::new(1.0, ui.visuals().text_color()),
+    ));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3600;
+    const DAY: i64 = 86400;
+
+    fn runs(last_run: i64, last_success: Option<i64>) -> ScheduleMetrics {
+        ScheduleMetrics {
+            last_run: Some(last_run),
+            last_success,
+            ..Default::default()
+        }
+    }
+
+    fn assess(m: Option<&ScheduleMetrics>, now: i64) -> Health {
+        Health::assess(24, m, &HealthSettings::default(), now)
+    }
+
+    #[test]
+    fn assess_by_age_of_the_last_success() {
+        let now = 100 * DAY;
+        let ok = |age| runs(now - age, Some(now - age));
+        assert_eq!(assess(Some(&ok(20 * HOUR)), now), Health::Green);
+        assert_eq!(assess(Some(&ok(36 * HOUR)), now), Health::Amber);
+        assert_eq!(assess(Some(&ok(72 * HOUR)), now), Health::Red);
+    }
+
+    #[test]
+    fn assess_failed_and_missing_runs() {
+        let now = 100 * DAY;
+        // Fresh success, but the run after it failed.
+        let failed_since = runs(now - HOUR, Some(now - 2 * HOUR));
+        assert_eq!(assess(Some(&failed_since), now), Health::Amber);
+        assert_eq!(assess(Some(&runs(now - HOUR, None)), now), Health::Red);
+        assert_eq!(
+            assess(Some(&ScheduleMetrics::default()), now),
+            Health::Unknown
+        );
+        assert_eq!(assess(None, now), Health::Unknown);
+    }
+
+    fn point(day: i64, free: Option<u64>) -> RunPoint {
+        RunPoint {
+            finished: day * DAY,
+            dest_free: free,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn forecast_from_shrinking_free_space() {
+        let history = [
+            point(10, Some(1000)),
+            point(11, Some(900)),
+            point(12, None),
+            point(13, Some(700)),
+        ];
+        let days = days_until_full(&history).unwrap();
+        assert!((days - 7.0).abs() < 1e-9, "{days}");
+    }
+
+    #[test]
+    fn no_forecast_without_a_trend() {
+        let growing = [
+            point(1, Some(100)),
+            point(2, Some(200)),
+            point(3, Some(300)),
+        ];
+        assert_eq!(days_until_full(&growing), None);
+        let too_few = [point(1, Some(300)), point(2, Some(200)), point(3, None)];
+        assert_eq!(days_until_full(&too_few), None);
+        let same_day = [
+            RunPoint {
+                finished: 0,
+                dest_free: Some(300),
+                ..Default::default()
+            },
+            RunPoint {
+                finished: 10,
+                dest_free: Some(200),
+                ..Default::default()
+            },
+            RunPoint {
+                finished: 20,
+                dest_free: Some(100),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(days_until_full(&same_day), None);
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::logfile::Level;
+use crate::{AppMsg, Schedule};
+
+/// How long output is still collected after a hook's shell has exited.
+const OUTPUT_GRACE: Duration = Duration::from_secs(2);
+
+/// Shell commands run around a scheduled backup, e.g. dumping a database
+/// before the copy and unmounting a drive after it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    pub pre: String,
+    pub post: String,
+    pub timeout_secs: u64,
+    /// Skip the backup when the pre-hook fails or times out.
+    pub abort_on_pre_failure: bool,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self {
+            pre: String::new(),
+            post: String::new(),
+            timeout_secs: 300,
+            abort_on_pre_failure: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Pre,
+    Post,
+}
+
+impl Phase {
+    fn label(self) -> &'static str {
+        match self {
+            Phase::Pre => "pre",
+            Phase::Post => "post",
+        }
+    }
+}
+
+/// Run the hook for `phase`, logging its output line by line. `result` is
+/// passed to post-hooks as `AUTOBACKUP_RESULT` (`success`, `failure` or
+/// `aborted`). An empty command counts as success.
+pub fn run(s: &Schedule, phase: Phase, result: Option<&str>, tx: &Sender<AppMsg>) -> bool {
+    let command = match phase {
+        Phase::Pre => s.hooks.pre.trim(),
+        Phase::Post => s.hooks.post.trim(),
+    };
+    if command.is_empty() {
+        return true;
+    }
+    let tag = format!("[{}-hook]", phase.label());
+    let _ = tx.send(AppMsg::Log(Level::Info, format!("{tag} {command}")));
+
+    let mut cmd = shell(command);
+    cmd.env("AUTOBACKUP_NAME", s.display_name())
+        .env("AUTOBACKUP_SOURCE", &s.source_dir)
+        .env("AUTOBACKUP_DEST", s.dest_label())
+        .env("AUTOBACKUP_DEST_KIND", s.dest_kind.label())
+        .env("AUTOBACKUP_PHASE", phase.label())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(result) = result {
+        cmd.env("AUTOBACKUP_RESULT", result);
+    }
+
+    let outcome = cmd
+        .spawn()
+        .map_err(anyhow::Error::from)
+        .and_then(|child| wait(child, Duration::from_secs(s.hooks.timeout_secs), &tag, tx));
+    match outcome {
+        Ok(()) => true,
+        Err(e) => {
+            let _ = tx.send(AppMsg::Log(Level::Error, format!("{tag} failed: {e}")));
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    use std::os::unix::process::CommandExt;
+
+    let mut cmd = Command::new("sh");
+    // Own process group, so a timeout also stops whatever the shell started.
+    cmd.arg("-c").arg(command).process_group(0);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+fn wait(mut child: Child, timeout: Duration, tag: &str, tx: &Sender<AppMsg>) -> anyhow::Result<()> {
+    // Both pipes are drained on their own threads so a chatty hook cannot
+    // block on a full pipe while we wait for it.
+    let readers: Vec<_> = [
+        child
+            .stdout
+            .take()
+            .map(|r| Box::new(r) as Box<dyn Read + Send>),
+        child
+            .stderr
+            .take()
+            .map(|r| Box::new(r) as Box<dyn Read + Send>),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|pipe| {
+        let tx = tx.clone();
+        let tag = tag.to_owned();
+        thread::spawn(move || {
+            for line in BufReader::new(pipe).lines().map_while(Result::ok) {
+                let _ = tx.send(AppMsg::Log(Level::Info, format!("{tag} {line}")));
+            }
+        })
+    })
+    .collect();
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if started.elapsed() >= timeout {
+            kill(&mut child);
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+    // Something the hook started in the background can keep the pipes open
+    // long after the shell is gone. Take what output follows promptly and
+    // leave the readers to finish whenever the pipes close.
+    let drained = Instant::now();
+    while readers.iter().any(|r| !r.is_finished()) && drained.elapsed() < OUTPUT_GRACE {
+        thread::sleep(Duration::from_millis(20));
+    }
+    for reader in readers.into_iter().filter(|r| r.is_finished()) {
+        let _ = reader.join();
+    }
+
+    match status {
+        None => bail!("timed out after {}s", timeout.as_secs()),
+        Some(status) if !status.success() => bail!("exited with {status}"),
+        Some(_) => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child) {
+    // SAFETY: plain kill(2); the group id is the child's pid.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(windows)]
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use tiny_http::{Header, Request, Response};
+
+/// Small HTTP listener handing each request to `handle` on one background
+/// thread. Stops when dropped.
+pub struct Server {
+    http: Arc<tiny_http::Server>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn start(
+        address: &str,
+        mut handle: impl FnMut(Request) + Send + 'static,
+    ) -> anyhow::Result<Self> {
+        // A listener stopped just before releases its port from a background
+        // thread, so give a restart on the same address a moment.
+        let mut attempts = 0;
+        let http = loop {
+            match tiny_http::Server::http(address.trim()) {
+                Ok(http) => break Arc::new(http),
+                Err(_) if attempts < 10 => {
+                    attempts += 1;
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => anyhow::bail!("{e}"),
+            }
+        };
+        let listener = http.clone();
+        let thread = std::thread::spawn(move || {
+            for request in listener.incoming_requests() {
+                handle(request);
+            }
+        });
+        Ok(Self {
+            http,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.http.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Answer `request`, ignoring clients that already went away.
+pub fn respond(request: Request, status: u16, content_type: &str, body: String) {
+    let mut response = Response::from_string(body).with_status_code(status);
+    if let Ok(header) = Header::from_bytes("Content-Type", content_type) {
+        response.add_header(header);
+    }
+    let _ = request.respond(response);
+}
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::destination::mtime_secs;
+use crate::{Schedule, config_dir};
+
+/// Files at least this large are tracked while being copied so an
+/// interrupted copy continues from the destination's `.partial` file.
+pub const LARGE_FILE: u64 = 64 * 1024 * 1024;
+
+/// How often finished files are flushed to disk during a run.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Large file being written when the journal was last saved. The resume
+/// offset is the length of its `.partial` file, which is exact even when the
+/// app was killed between saves.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InProgress {
+    pub rel: PathBuf,
+    pub size: u64,
+    pub mtime: i64,
+}
+
+/// Size and mtime of a source file when it was copied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Version {
+    size: u64,
+    mtime: i64,
+}
+
+/// Progress of a full backup run, kept in the config folder until the run
+/// has walked the whole source.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Journal {
+    source: String,
+    dest: String,
+    /// Finished files and the source version they were copied at.
+    done: HashMap<PathBuf, Version>,
+    current: Option<InProgress>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    #[serde(skip)]
+    last_save: Option<Instant>,
+}
+
+impl Journal {
+    /// The journal left by an interrupted run of `s`, or an empty one.
+    pub fn load(s: &Schedule) -> Self {
+        let dest = s.dest_label();
+        let path = journal_path(&s.source_dir, &dest);
+        let loaded = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|text| serde_json::from_str::<Journal>(&text).ok())
+            .filter(|j| j.source == s.source_dir && j.dest == dest);
+        Self {
+            path,
+            ..loaded.unwrap_or(Self {
+                source: s.source_dir.clone(),
+                dest,
+                ..Self::default()
+            })
+        }
+    }
+
+    pub fn done_count(&self) -> usize {
+        self.done.len()
+    }
+
+    /// Whether the interrupted run already copied this version of the file.
+    pub fn is_done(&self, rel: &Path, meta: &fs::Metadata) -> bool {
+        self.done.get(rel) == Some(&version(meta))
+    }
+
+    /// Whether `rel` is the large file the interrupted run was writing, and
+    /// the source has not changed since.
+    pub fn is_current(&self, rel: &Path, meta: &fs::Metadata) -> bool {
+        self.current.as_ref() == Some(&in_progress(rel, meta))
+    }
+
+    /// Record that a large file is about to be written. Saved right away,
+    /// since the copy may take a long time.
+    pub fn begin(&mut self, rel: &Path, meta: &fs::Metadata) -> anyhow::Result<()> {
+        self.current = Some(in_progress(rel, meta));
+        self.save()
+    }
+
+    pub fn finish(&mut self, rel: &Path, meta: &fs::Metadata) -> anyhow::Result<()> {
+        self.done.insert(rel.to_path_buf(), version(meta));
+        if self.current.as_ref().is_some_and(|c| c.rel == rel) {
+            self.current = None;
+        }
+        if self.last_save.is_none_or(|t| t.elapsed() >= SAVE_INTERVAL) {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Written aside and renamed so a crash mid-save keeps the old journal.
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        fs::rename(&tmp, path)?;
+        self.last_save = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Drop the journal once the whole source has been walked.
+    pub fn remove(self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn version(meta: &fs::Metadata) -> Version {
+    Version {
+        size: meta.len(),
+        mtime: mtime_secs(meta),
+    }
+}
+
+fn in_progress(rel: &Path, meta: &fs::Metadata) -> InProgress {
+    InProgress {
+        rel: rel.to_path_buf(),
+        size: meta.len(),
+        mtime: mtime_secs(meta),
+    }
+}
+
+/// One file per source/destination pair, named by a hash since both are
+/// arbitrary paths or URLs.
+fn journal_path(source: &str, dest: &str) -> Option<PathBuf> {
+    let digest = Sha256::digest(format!("{source}\n{dest}").as_bytes());
+    let name = format!("{}.json", &hex::encode(digest)[..16]);
+    config_dir().map(|dir| dir.join("journal").join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    /// The metadata of a scratch file of `size` bytes modified at Unix time
+    /// `mtime`.
+    fn source_file(name: &str, size: u64, mtime: u64) -> fs::Metadata {
+        let path =
+            std::env::temp_dir().join(format!("autobackup-journal-{}-{name}", std::process::id()));
+        let file = File::create(&path).unwrap();
+        file.set_len(size).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(mtime))
+            .unwrap();
+        let meta = fs::metadata(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        meta
+    }
+
+    /// The journal as the next run reads it back.
+    fn reloaded(journal: &Journal) -> Journal {
+        serde_json::from_str(&serde_json::to_string(journal).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn done_files_are_skipped_while_unchanged() {
+        let rel = Path::new("docs/a.txt");
+        let mut journal = Journal::default();
+        let copied = source_file("done", 10, 1_700_000_000);
+        assert!(!journal.is_done(rel, &copied));
+        journal.finish(rel, &copied).unwrap();
+
+        let journal = reloaded(&journal);
+        assert!(journal.is_done(rel, &copied));
+        assert!(!journal.is_done(Path::new("docs/b.txt"), &copied));
+        assert!(!journal.is_done(rel, &source_file("grown", 11, 1_700_000_000)));
+        assert!(!journal.is_done(rel, &source_file("touched", 10, 1_700_000_001)));
+    }
+
+    #[test]
+    fn an_unfinished_large_file_is_resumable() {
+        let rel = Path::new("video.mkv");
+        let meta = source_file("large", 100, 1_700_000_000);
+        let mut journal = Journal::default();
+        journal.begin(rel, &meta).unwrap();
+
+        let mut journal = reloaded(&journal);
+        assert!(journal.is_current(rel, &meta));
+        assert!(!journal.is_current(Path::new("other.mkv"), &meta));
+        assert!(!journal.is_current(rel, &source_file("changed", 101, 1_700_000_000)));
+
+        journal.finish(rel, &meta).unwrap();
+        assert!(!journal.is_current(rel, &meta));
+        assert!(journal.is_done(rel, &meta));
+    }
+}
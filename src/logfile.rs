@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::{AppMsg, config_dir};
+
+/// The live file is rotated once it would grow past this size.
+const MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+/// Rotated files kept next to the live one (`AutoBackup.1.log` is the newest).
+const KEEP_ROTATED: usize = 4;
+/// Records shown in the Logs panel right after startup.
+pub const HISTORY_RECORDS: usize = 1000;
+/// Records kept in memory for the Logs panel; older ones are only on disk.
+pub const MEMORY_RECORDS: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub const ALL: [Level; 3] = [Level::Info, Level::Warn, Level::Error];
+
+    /// Name of the Logs panel filter showing this level and above.
+    pub fn filter_label(self) -> &'static str {
+        match self {
+            Level::Info => "All levels",
+            Level::Warn => "Warnings and errors",
+            Level::Error => "Errors only",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+/// A backup run's identity, attached to everything it logs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunTag {
+    /// `Schedule::id`.
+    pub schedule: u64,
+    pub run: u64,
+}
+
+/// One line of the log file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Local time, `%Y-%m-%d %H:%M:%S`.
+    pub time: String,
+    #[serde(default)]
+    pub level: Level,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<u64>,
+    pub message: String,
+}
+
+impl LogRecord {
+    pub fn new(level: Level, tag: Option<RunTag>, message: String) -> Self {
+        Self {
+            time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            level,
+            schedule: tag.map(|t| t.schedule),
+            run: tag.map(|t| t.run),
+            message,
+        }
+    }
+
+    /// The line shown in the Logs panel.
+    pub fn line(&self) -> String {
+        match self.level {
+            Level::Info => format!("[{}] {}", self.time, self.message),
+            level => format!("[{}] {} {}", self.time, level.label(), self.message),
+        }
+    }
+}
+
+/// Id for a new run: milliseconds since the epoch, unique enough per
+/// schedule and sortable across restarts.
+pub fn new_run_id() -> u64 {
+    Local::now().timestamp_millis().max(0) as u64
+}
+
+/// Sender for a backup thread that tags its `AppMsg::Log` messages with
+/// `tag` and passes everything else on unchanged, in order.
+pub fn tagged_sender(tx: &Sender<AppMsg>, tag: RunTag) -> Sender<AppMsg> {
+    let (run_tx, run_rx) = mpsc::channel();
+    let tx = tx.clone();
+    // Ends once the run and every thread it started dropped their sender.
+    std::thread::spawn(move || {
+        for msg in run_rx {
+            let msg = match msg {
+                AppMsg::Log(level, text) => AppMsg::RunLog(tag, level, text),
+                other => other,
+            };
+            if tx.send(msg).is_err() {
+                break;
+            }
+        }
+    });
+    run_tx
+}
+
+/// Append-only JSON lines log in the config folder, rotated by size.
+pub struct LogFile {
+    path: PathBuf,
+    file: Option<File>,
+    size: u64,
+}
+
+impl LogFile {
+    pub fn new() -> Option<Self> {
+        let path = config_dir()?.join("logs").join("AutoBackup.log");
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        Some(Self {
+            path,
+            file: None,
+            size,
+        })
+    }
+
+    pub fn append(&mut self, record: &LogRecord) -> io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        if self.size > 0 && self.size + line.len() as u64 > MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                if let Some(parent) = self.path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                self.file.insert(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&self.path)?,
+                )
+            }
+        };
+        file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        let _ = fs::remove_file(self.rotated(KEEP_ROTATED));
+        for n in (1..KEEP_ROTATED).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        self.path.with_extension(format!("{n}.log"))
+    }
+
+    /// The last `limit` records, oldest first, reaching into the newest
+    /// rotated file when the live one was just rotated. Lines that are not
+    /// valid records (a crash mid-write) are skipped.
+    pub fn recent(&self, limit: usize) -> Vec<LogRecord> {
+        let mut records = VecDeque::with_capacity(limit);
+        for path in [self.rotated(1), self.path.clone()] {
+            let Ok(file) = File::open(&path) else {
+                continue;
+            };
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                if let Ok(record) = serde_json::from_str::<LogRecord>(&line) {
+                    if records.len() == limit {
+                        records.pop_front();
+                    }
+                    records.push_back(record);
+                }
+            }
+        }
+        records.into()
+    }
+}
@@ -1,26 +1,95 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader, Write};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::process::Command;
 use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::{Duration, Instant};
 
-use chrono::{Local, NaiveDateTime};
+use chrono::{DateTime, Local, NaiveDateTime};
 use eframe::egui::{
     self, Align, Button, Color32, Context, Layout, RichText, TextEdit, TopBottomPanel, Ui,
 };
 use egui_extras::{Column, TableBuilder};
 use rfd::FileDialog;
-
-#[derive(Clone, Debug)]
+use serde::{Deserialize, Serialize};
+
+mod api;
+mod copy;
+mod destination;
+mod email;
+mod health;
+mod hooks;
+mod http;
+mod journal;
+mod logfile;
+mod metrics;
+mod notifications;
+mod queue;
+mod report;
+mod source;
+mod throttle;
+mod watch;
+mod webhook;
+
+use api::{ApiReply, ApiRequest, ApiSettings};
+use copy::{Copier, CopyOptions, SymlinkPolicy};
+use destination::{DestKind, Destination, S3Config, SftpConfig, SpaceCheck, WebDavConfig};
+use email::{Digest, DigestBatch, EmailSettings, Security};
+use health::{Health, HealthSettings};
+use hooks::{Hooks, Phase};
+use journal::Journal;
+use logfile::{Level, LogFile, LogRecord, RunTag};
+use metrics::{Metrics, MetricsSettings};
+use notifications::Notifications;
+use queue::{Dependency, JobKind, JobQueue, Priority, Trigger};
+use report::{Outcome, RunInfo, RunReport, RunSummary};
+use source::SnapshotMode;
+use throttle::Throttle;
+use webhook::WebhookSettings;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 struct Schedule {
+    /// Stable identifier, used by other schedules to chain after this one.
+    id: u64,
+    /// Optional label; the source folder name is used when empty.
+    name: String,
     source_dir: String,
     dest_dir: String,
     period_hours: i32,
     skip_file_exts_label: String, // like "*.log *.tmp"
     skip_folders_label: String,   // space-separated folder names
     use_zip: bool,
+    mirror: bool,
+    /// Back up changed files as they happen, in addition to the period.
+    watch: bool,
+    watch_debounce_secs: u64,
+    symlinks: SymlinkPolicy,
+    snapshot: SnapshotMode,
+    /// Which waiting backup starts first when slots are scarce.
+    priority: Priority,
+    /// Also run whenever a full backup of another schedule finishes with the
+    /// given result.
+    depends_on: Option<Dependency>,
+    /// Extra attempts for a file that failed or changed while being copied.
+    retries: u32,
+    /// Wait before the first retry, doubled for each further one.
+    retry_delay_secs: u64,
+    dest_kind: DestKind,
+    /// Compare what a run will write with the free space at a local
+    /// destination before copying.
+    space_check: SpaceCheck,
+    sftp: SftpConfig,
+    s3: S3Config,
+    webdav: WebDavConfig,
+    hooks: Hooks,
+    throttle: Throttle,
+    notifications: Notifications,
+    #[serde(skip)]
     last_time: NaiveDateTime,
+    #[serde(skip)]
     is_running: bool,
 }
 
@@ -34,21 +103,183 @@ impl Schedule {
         use_zip: bool,
     ) -> Self {
         Self {
+            id: 0,
+            name: String::new(),
             source_dir,
             dest_dir,
             period_hours,
             skip_file_exts_label,
             skip_folders_label,
             use_zip,
+            mirror: false,
+            watch: false,
+            watch_debounce_secs: 5,
+            symlinks: SymlinkPolicy::default(),
+            snapshot: SnapshotMode::default(),
+            priority: Priority::default(),
+            depends_on: None,
+            retries: 3,
+            retry_delay_secs: 2,
+            dest_kind: DestKind::Local,
+            space_check: SpaceCheck::default(),
+            sftp: SftpConfig::default(),
+            s3: S3Config::default(),
+            webdav: WebDavConfig::default(),
+            hooks: Hooks::default(),
+            throttle: Throttle::default(),
+            notifications: Notifications::default(),
             last_time: Local::now().naive_local(),
             is_running: false,
         }
     }
+
+    fn display_name(&self) -> String {
+        if self.name.trim().is_empty() {
+            source_leaf(&self.source_dir)
+        } else {
+            self.name.trim().to_owned()
+        }
+    }
+
+    /// Why the remote destination settings are incomplete, if they are.
+    fn remote_error(&self) -> Option<&'static str> {
+        match self.dest_kind {
+            DestKind::Local => None,
+            DestKind::Sftp => {
+                let c = &self.sftp;
+                if c.host.trim().is_empty()
+                    || c.user.trim().is_empty()
+                    || c.remote_path.trim().is_empty()
+                {
+                    Some("SFTP host, user and remote path are required")
+                } else {
+                    None
+                }
+            }
+            DestKind::S3 => {
+                let c = &self.s3;
+                if c.endpoint.trim().is_empty() || c.bucket.trim().is_empty() {
+                    Some("S3 endpoint and bucket are required")
+                } else {
+                    None
+                }
+            }
+            DestKind::WebDav => {
+                if self.webdav.url.trim().is_empty() {
+                    Some("WebDAV URL is required")
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn dest_label(&self) -> String {
+        match self.dest_kind {
+            DestKind::Local => self.dest_dir.clone(),
+            DestKind::Sftp => self.sftp.describe(),
+            DestKind::S3 => self.s3.describe(),
+            DestKind::WebDav => self.webdav.describe(),
+        }
+    }
+
+    /// The destination as a path for telling overlapping ones apart: a local
+    /// folder made absolute without `.` and `..`, a remote one as its URL.
+    fn dest_path(&self) -> PathBuf {
+        if self.dest_kind.is_remote() {
+            return PathBuf::from(self.dest_label());
+        }
+        let dir =
+            std::path::absolute(&self.dest_dir).unwrap_or_else(|_| self.dest_dir.clone().into());
+        let mut path = PathBuf::new();
+        for part in dir.components() {
+            match part {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    path.pop();
+                }
+                part => path.push(part),
+            }
+        }
+        path
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::new(
+            String::new(),
+            String::new(),
+            24,
+            String::new(),
+            String::new(),
+            false,
+        )
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SavedData {
+    schedules: Vec<Schedule>,
+    settings: Settings,
+}
+
+/// Options shared by all schedules.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    /// Backups allowed to run at the same time; the rest wait in the queue.
+    max_concurrent: usize,
+    email: EmailSettings,
+    webhook: WebhookSettings,
+    metrics: MetricsSettings,
+    api: ApiSettings,
+    health: HealthSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 2,
+            email: EmailSettings::default(),
+            webhook: WebhookSettings::default(),
+            metrics: MetricsSettings::default(),
+            api: ApiSettings::default(),
+            health: HealthSettings::default(),
+        }
+    }
 }
 
 enum AppMsg {
-    Log(String),
-    BackupFinished(usize, bool),
+    Log(Level, String),
+    /// `Log` from a backup run, tagged by `logfile::tagged_sender`.
+    RunLog(RunTag, Level, String),
+    BackupFinished(usize, RunSummary),
+    /// Paths under the source of the schedule with this id that changed,
+    /// relative to the source.
+    SourceChanged(u64, Vec<PathBuf>),
+    ChangesBackedUp(usize),
+    /// A control API call, answered on the sender.
+    Api(ApiRequest, Sender<ApiReply>),
+    /// Whether the daily digest mail for this batch went out.
+    DigestSent(DigestBatch, bool),
+    /// Free bytes at local destinations by `Schedule::id`.
+    FreeSpace(HashMap<u64, u64>),
+}
+
+/// Changes collected for a watched schedule until its debounce window passes.
+struct PendingChanges {
+    paths: HashSet<PathBuf>,
+    last_event: Instant,
+}
+
+/// A schedule's watcher with the settings it was started from, so it is only
+/// rebuilt when they change.
+struct Watched {
+    settings: serde_json::Value,
+    /// Only held: dropping it stops the watch.
+    _watcher: Option<notify::RecommendedWatcher>,
 }
 
 struct AppState {
@@ -56,6 +287,7 @@ struct AppState {
     selected_index: Option<usize>,
 
     // input fields
+    input_name: String,
     input_source_dir: String,
     input_dest_dir: String,
     input_period_hours: String,
@@ -64,8 +296,53 @@ struct AppState {
     label_skip_files: String,
     label_skip_folders: String,
     input_use_zip: bool,
-
-    logs: Vec<String>,
+    input_mirror: bool,
+    input_watch: bool,
+    input_watch_debounce_secs: u64,
+    input_symlinks: SymlinkPolicy,
+    input_snapshot: SnapshotMode,
+    input_priority: Priority,
+    input_depends_on: Option<u64>,
+    input_trigger: Trigger,
+    input_retries: u32,
+    input_retry_delay_secs: u64,
+    input_dest_kind: DestKind,
+    input_space_check: SpaceCheck,
+    input_sftp: SftpConfig,
+    input_s3: S3Config,
+    input_webdav: WebDavConfig,
+    input_hooks: Hooks,
+    input_throttle: Throttle,
+    input_rate_profiles: String,
+    input_notifications: Notifications,
+
+    logs: Vec<LogRecord>,
+    log_file: Option<LogFile>,
+    log_min_level: Level,
+    /// `Schedule::id` to show logs of, or all.
+    log_schedule: Option<u64>,
+    log_search: String,
+    /// Title and text of the report shown in the "Last report" window.
+    report_view: Option<(String, String)>,
+
+    settings: Settings,
+    queue: JobQueue,
+    digest: Digest,
+    /// Shared with the `/metrics` listener.
+    metrics: Arc<Mutex<Metrics>>,
+    metrics_server: Option<http::Server>,
+    api_server: Option<api::ApiServer>,
+    /// Lets API calls wake the window while it is idle.
+    egui_ctx: Option<Context>,
+    /// Free bytes at local destinations by `Schedule::id`, for the Health view.
+    free_space: HashMap<u64, u64>,
+    free_space_checked: Option<Instant>,
+    /// A check is still running, e.g. stuck on an unresponsive network share.
+    free_space_checking: bool,
+
+    /// Both by `Schedule::id`.
+    watchers: HashMap<u64, Watched>,
+    pending_changes: HashMap<u64, PendingChanges>,
 
     tx: Sender<AppMsg>,
     rx: Receiver<AppMsg>,
@@ -76,10 +353,16 @@ struct AppState {
 impl Default for AppState {
     fn default() -> Self {
         let (tx, rx) = mpsc::channel();
+        let log_file = LogFile::new();
+        let logs = log_file
+            .as_ref()
+            .map(|f| f.recent(logfile::HISTORY_RECORDS))
+            .unwrap_or_default();
         let mut app = Self {
             schedules: Vec::new(),
             selected_index: None,
 
+            input_name: String::new(),
             input_source_dir: String::new(),
             input_dest_dir: default_backup_root(),
             input_period_hours: "24".to_owned(),
@@ -88,8 +371,46 @@ impl Default for AppState {
             label_skip_files: String::new(),
             label_skip_folders: String::new(),
             input_use_zip: false,
-
-            logs: Vec::new(),
+            input_mirror: false,
+            input_watch: false,
+            input_watch_debounce_secs: 5,
+            input_symlinks: SymlinkPolicy::default(),
+            input_snapshot: SnapshotMode::default(),
+            input_priority: Priority::default(),
+            input_depends_on: None,
+            input_trigger: Trigger::default(),
+            input_retries: 3,
+            input_retry_delay_secs: 2,
+            input_dest_kind: DestKind::Local,
+            input_space_check: SpaceCheck::default(),
+            input_sftp: SftpConfig::default(),
+            input_s3: S3Config::default(),
+            input_webdav: WebDavConfig::default(),
+            input_hooks: Hooks::default(),
+            input_throttle: Throttle::default(),
+            input_rate_profiles: String::new(),
+            input_notifications: Notifications::default(),
+
+            logs,
+            log_file,
+            log_min_level: Level::Info,
+            log_schedule: None,
+            log_search: String::new(),
+            report_view: None,
+
+            settings: Settings::default(),
+            queue: JobQueue::default(),
+            digest: Digest::load(),
+            metrics: Arc::new(Mutex::new(Metrics::load())),
+            metrics_server: None,
+            api_server: None,
+            egui_ctx: None,
+            free_space: HashMap::new(),
+            free_space_checked: None,
+            free_space_checking: false,
+
+            watchers: HashMap::new(),
+            pending_changes: HashMap::new(),
 
             tx,
             rx,
@@ -97,6 +418,9 @@ impl Default for AppState {
             last_tick: Instant::now(),
         };
         app.load_data();
+        app.restart_watchers();
+        app.metrics().sync(&app.schedules);
+        app.restart_metrics_server();
         app
     }
 }
@@ -109,36 +433,56 @@ impl AppState {
                 ui.label("Source folder");
                 ui.horizontal(|ui| {
                     ui.add(TextEdit::singleline(&mut self.input_source_dir).desired_width(350.0));
-                    if ui.button("Choose...").clicked() {
-                        if let Some(path) = FileDialog::new().pick_folder() {
-                            self.input_source_dir = path.to_string_lossy().to_string();
-                            if !self.input_source_dir.is_empty() {
-                                if self.input_dest_dir.is_empty()
-                                    || self.input_dest_dir == default_backup_root()
-                                {
-                                    self.input_dest_dir =
-                                        default_dest_for_source(&self.input_source_dir);
-                                }
-                            }
+                    if ui.button("Choose...").clicked()
+                        && let Some(path) = FileDialog::new().pick_folder()
+                    {
+                        self.input_source_dir = path.to_string_lossy().to_string();
+                        if !self.input_source_dir.is_empty()
+                            && (self.input_dest_dir.is_empty()
+                                || self.input_dest_dir == default_backup_root())
+                        {
+                            self.input_dest_dir = default_dest_for_source(&self.input_source_dir);
                         }
                     }
                 });
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.add(
+                        TextEdit::singleline(&mut self.input_name)
+                            .hint_text(source_leaf(&self.input_source_dir))
+                            .desired_width(200.0),
+                    );
+                });
             });
 
             ui.separator();
 
             // Destination
             ui.vertical(|ui| {
-                ui.label("Destination folder");
                 ui.horizontal(|ui| {
-                    ui.add(TextEdit::singleline(&mut self.input_dest_dir).desired_width(350.0));
-                    if ui.button("Choose...").clicked() {
-                        if let Some(path) = FileDialog::new().pick_folder() {
-                            if self.input_source_dir == path.to_string_lossy() {
-                                self.log("Destination cannot equal source");
-                            } else {
-                                self.input_dest_dir = path.to_string_lossy().to_string();
+                    ui.label("Destination");
+                    egui::ComboBox::from_id_source("dest_kind")
+                        .selected_text(self.input_dest_kind.label())
+                        .show_ui(ui, |ui| {
+                            for kind in DestKind::ALL {
+                                ui.selectable_value(&mut self.input_dest_kind, kind, kind.label());
                             }
+                        });
+                });
+                ui.horizontal(|ui| {
+                    ui.add_enabled(
+                        !self.input_dest_kind.is_remote(),
+                        TextEdit::singleline(&mut self.input_dest_dir).desired_width(350.0),
+                    );
+                    if ui
+                        .add_enabled(!self.input_dest_kind.is_remote(), Button::new("Choose..."))
+                        .clicked()
+                        && let Some(path) = FileDialog::new().pick_folder()
+                    {
+                        if self.input_source_dir == path.to_string_lossy() {
+                            self.log("Destination cannot equal source");
+                        } else {
+                            self.input_dest_dir = path.to_string_lossy().to_string();
                         }
                     }
                 });
@@ -158,9 +502,147 @@ impl AppState {
             ui.vertical(|ui| {
                 ui.label("Options");
                 ui.checkbox(&mut self.input_use_zip, "Zip after copy (7z)");
+                ui.checkbox(&mut self.input_mirror, "Mirror (delete removed files)");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.input_watch, "Watch for changes");
+                    ui.add_enabled(
+                        self.input_watch,
+                        egui::DragValue::new(&mut self.input_watch_debounce_secs)
+                            .clamp_range(1..=3600)
+                            .suffix(" s"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Retries");
+                    ui.add(egui::DragValue::new(&mut self.input_retries).clamp_range(0..=10));
+                    ui.label("after");
+                    ui.add(
+                        egui::DragValue::new(&mut self.input_retry_delay_secs)
+                            .clamp_range(1..=600)
+                            .suffix(" s"),
+                    );
+                });
+                egui::ComboBox::from_id_source("symlinks")
+                    .selected_text(self.input_symlinks.label())
+                    .show_ui(ui, |ui| {
+                        for policy in SymlinkPolicy::ALL {
+                            ui.selectable_value(&mut self.input_symlinks, policy, policy.label());
+                        }
+                    });
+                egui::ComboBox::from_id_source("snapshot")
+                    .selected_text(self.input_snapshot.label())
+                    .show_ui(ui, |ui| {
+                        for mode in SnapshotMode::ALL {
+                            ui.selectable_value(&mut self.input_snapshot, mode, mode.label());
+                        }
+                    });
+                egui::ComboBox::from_id_source("priority")
+                    .selected_text(format!("{} priority", self.input_priority.label()))
+                    .show_ui(ui, |ui| {
+                        for priority in Priority::ALL {
+                            ui.selectable_value(
+                                &mut self.input_priority,
+                                priority,
+                                priority.label(),
+                            );
+                        }
+                    });
+                ui.add_enabled_ui(!self.input_dest_kind.is_remote(), |ui| {
+                    egui::ComboBox::from_id_source("space_check")
+                        .selected_text(self.input_space_check.label())
+                        .show_ui(ui, |ui| {
+                            for check in SpaceCheck::ALL {
+                                ui.selectable_value(
+                                    &mut self.input_space_check,
+                                    check,
+                                    check.label(),
+                                );
+                            }
+                        });
+                });
             });
         });
 
+        if self.input_dest_kind == DestKind::Sftp {
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                ui.label("Host");
+                ui.add(TextEdit::singleline(&mut self.input_sftp.host).desired_width(160.0));
+                ui.label("Port");
+                ui.add(egui::DragValue::new(&mut self.input_sftp.port).clamp_range(1..=65535));
+                ui.label("User");
+                ui.add(TextEdit::singleline(&mut self.input_sftp.user).desired_width(100.0));
+                ui.label("Key file");
+                ui.add(
+                    TextEdit::singleline(&mut self.input_sftp.key_file)
+                        .hint_text("empty = ssh-agent")
+                        .desired_width(200.0),
+                );
+                if ui.button("Choose...").clicked()
+                    && let Some(path) = FileDialog::new().pick_file()
+                {
+                    self.input_sftp.key_file = path.to_string_lossy().to_string();
+                }
+                ui.label("Remote path");
+                ui.add(
+                    TextEdit::singleline(&mut self.input_sftp.remote_path)
+                        .hint_text("/srv/backup")
+                        .desired_width(200.0),
+                );
+            });
+        }
+
+        if self.input_dest_kind == DestKind::S3 {
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                ui.label("Endpoint");
+                ui.add(
+                    TextEdit::singleline(&mut self.input_s3.endpoint)
+                        .hint_text("http://127.0.0.1:9000")
+                        .desired_width(200.0),
+                );
+                ui.label("Region");
+                ui.add(TextEdit::singleline(&mut self.input_s3.region).desired_width(90.0));
+                ui.label("Bucket");
+                ui.add(TextEdit::singleline(&mut self.input_s3.bucket).desired_width(120.0));
+                ui.label("Prefix");
+                ui.add(TextEdit::singleline(&mut self.input_s3.prefix).desired_width(120.0));
+                ui.label("Access key");
+                ui.add(
+                    TextEdit::singleline(&mut self.input_s3.access_key)
+                        .hint_text("AWS_ACCESS_KEY_ID")
+                        .desired_width(120.0),
+                );
+                ui.label("Secret key");
+                ui.add(
+                    TextEdit::singleline(&mut self.input_s3.secret_key)
+                        .password(true)
+                        .hint_text("AWS_SECRET_ACCESS_KEY")
+                        .desired_width(120.0),
+                );
+            });
+        }
+
+        if self.input_dest_kind == DestKind::WebDav {
+            ui.add_space(4.0);
+            ui.horizontal(|ui| {
+                ui.label("URL");
+                ui.add(
+                    TextEdit::singleline(&mut self.input_webdav.url)
+                        .hint_text("https://cloud.example.com/remote.php/dav/files/me/Backups")
+                        .desired_width(420.0),
+                );
+                ui.label("User");
+                ui.add(TextEdit::singleline(&mut self.input_webdav.user).desired_width(120.0));
+                ui.label("Password");
+                ui.add(
+                    TextEdit::singleline(&mut self.input_webdav.password)
+                        .password(true)
+                        .desired_width(120.0),
+                );
+            });
+        }
+
         ui.add_space(8.0);
 
         ui.horizontal(|ui| {
@@ -195,6 +677,96 @@ impl AppState {
             ui.label(RichText::new(self.label_skip_folders.clone()).color(Color32::LIGHT_BLUE));
         });
 
+        ui.horizontal(|ui| {
+            ui.label("Before backup");
+            ui.add(
+                TextEdit::singleline(&mut self.input_hooks.pre)
+                    .hint_text("e.g. pg_dump mydb > db.sql")
+                    .desired_width(240.0),
+            );
+            ui.label("After backup");
+            ui.add(
+                TextEdit::singleline(&mut self.input_hooks.post)
+                    .hint_text("e.g. umount /mnt/usb")
+                    .desired_width(240.0),
+            );
+            ui.label("Timeout");
+            ui.add(
+                egui::DragValue::new(&mut self.input_hooks.timeout_secs)
+                    .clamp_range(1..=86400)
+                    .suffix(" s"),
+            );
+            ui.checkbox(
+                &mut self.input_hooks.abort_on_pre_failure,
+                "Skip backup if the first command fails",
+            );
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Run after");
+            let upstream_name = |id: Option<u64>| {
+                id.and_then(|id| self.schedules.iter().find(|s| s.id == id))
+                    .map_or_else(|| "Nothing".to_owned(), Schedule::display_name)
+            };
+            egui::ComboBox::from_id_source("depends_on")
+                .selected_text(upstream_name(self.input_depends_on))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.input_depends_on, None, "Nothing");
+                    for s in &self.schedules {
+                        ui.selectable_value(
+                            &mut self.input_depends_on,
+                            Some(s.id),
+                            s.display_name(),
+                        );
+                    }
+                });
+            ui.add_enabled_ui(self.input_depends_on.is_some(), |ui| {
+                egui::ComboBox::from_id_source("trigger")
+                    .selected_text(self.input_trigger.label())
+                    .show_ui(ui, |ui| {
+                        for trigger in Trigger::ALL {
+                            ui.selectable_value(&mut self.input_trigger, trigger, trigger.label());
+                        }
+                    });
+            });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Speed limit");
+            ui.add(
+                egui::DragValue::new(&mut self.input_throttle.limit_mbps)
+                    .clamp_range(0.0..=10000.0)
+                    .speed(0.5)
+                    .suffix(" MB/s"),
+            );
+            ui.label("0 = unlimited");
+            ui.separator();
+            ui.label("By hour");
+            ui.add(
+                TextEdit::singleline(&mut self.input_rate_profiles)
+                    .hint_text("e.g. 9-18=5 18-9=0")
+                    .desired_width(180.0),
+            );
+            // Only Linux lets a single thread lower its I/O priority.
+            if cfg!(target_os = "linux") {
+                ui.separator();
+                ui.checkbox(
+                    &mut self.input_throttle.low_priority,
+                    "Low CPU/disk priority",
+                );
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Notify on");
+            ui.checkbox(&mut self.input_notifications.on_failure, "failure");
+            ui.checkbox(
+                &mut self.input_notifications.on_errors,
+                "completion with errors",
+            );
+            ui.checkbox(&mut self.input_notifications.on_success, "success");
+        });
+
         ui.add_space(8.0);
 
         ui.horizontal(|ui| {
@@ -210,6 +782,9 @@ impl AppState {
             if ui.add(Button::new("Run now")).clicked() {
                 self.action_run_now();
             }
+            if ui.add(Button::new("Last report")).clicked() {
+                self.action_last_report();
+            }
         });
     }
 
@@ -250,10 +825,15 @@ impl AppState {
                     let is_selected = self.selected_index == Some(idx);
                     body.row(text_height, |mut row| {
                         row.col(|ui| {
+                            let label = if sched.name.trim().is_empty() {
+                                sched.source_dir.clone()
+                            } else {
+                                format!("{} ({})", sched.name.trim(), sched.source_dir)
+                            };
                             let text = if is_selected {
-                                RichText::new(&sched.source_dir).strong()
+                                RichText::new(label).strong()
                             } else {
-                                RichText::new(&sched.source_dir)
+                                RichText::new(label)
                             };
                             if ui
                                 .add(egui::SelectableLabel::new(is_selected, text))
@@ -263,7 +843,7 @@ impl AppState {
                             }
                         });
                         row.col(|ui| {
-                            ui.label(&sched.dest_dir);
+                            ui.label(sched.dest_label());
                         });
                         row.col(|ui| {
                             ui.horizontal_wrapped(|ui| {
@@ -274,20 +854,54 @@ impl AppState {
                                 ui.label(&sched.skip_folders_label);
                                 ui.separator();
                                 ui.label(if sched.use_zip { "Zip" } else { "No zip" });
+                                if sched.mirror {
+                                    ui.separator();
+                                    ui.label("Mirror");
+                                }
+                                if sched.symlinks != SymlinkPolicy::default() {
+                                    ui.separator();
+                                    ui.label(sched.symlinks.label());
+                                }
+                                if sched.snapshot != SnapshotMode::None {
+                                    ui.separator();
+                                    ui.label(sched.snapshot.label());
+                                }
+                                if let Some(dep) = sched.depends_on {
+                                    let upstream = self
+                                        .schedules
+                                        .iter()
+                                        .find(|s| s.id == dep.schedule)
+                                        .map(Schedule::display_name)
+                                        .unwrap_or_default();
+                                    ui.separator();
+                                    ui.label(format!("After {upstream} {}", dep.when.label()));
+                                }
+                                if sched.priority != Priority::Normal {
+                                    ui.separator();
+                                    ui.label(format!("{} priority", sched.priority.label()));
+                                }
+                                if sched.throttle.is_limited() {
+                                    ui.separator();
+                                    ui.label("Throttled");
+                                }
+                                if sched.watch {
+                                    ui.separator();
+                                    ui.label(format!("Watch {}s", sched.watch_debounce_secs));
+                                }
                                 ui.separator();
                                 ui.label(format!(
                                     "{}",
                                     sched.last_time.format("%Y-%m-%d %H:%M:%S")
                                 ));
                                 ui.separator();
-                                ui.colored_label(
-                                    if sched.is_running {
-                                        Color32::YELLOW
-                                    } else {
-                                        Color32::GREEN
-                                    },
-                                    if sched.is_running { "Running" } else { "Idle" },
-                                );
+                                let (color, status) = if sched.is_running {
+                                    (Color32::YELLOW, "Running")
+                                } else if self.queue.is_waiting(idx) {
+                                    (Color32::LIGHT_BLUE, "Queued")
+                                } else {
+                                    (Color32::GREEN, "Idle")
+                                };
+                                ui.colored_label(color, status);
                             });
                         });
                     });
@@ -303,13 +917,510 @@ impl AppState {
         ctx.request_repaint_after(Duration::from_millis(500));
     }
 
+    fn ui_health(&mut self, ui: &mut Ui) {
+        egui::CollapsingHeader::new("Health")
+            .id_source("health")
+            .default_open(true)
+            .show(ui, |ui| {
+                let mut changed = false;
+                ui.horizontal(|ui| {
+                    let settings = &mut self.settings.health;
+                    ui.label("Amber once the last success is older than");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut settings.amber_after)
+                                .clamp_range(1.0..=100.0)
+                                .speed(0.1)
+                                .suffix("×"),
+                        )
+                        .changed();
+                    ui.label("the period, red after");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut settings.red_after)
+                                .clamp_range(settings.amber_after..=100.0)
+                                .speed(0.1)
+                                .suffix("×"),
+                        )
+                        .changed();
+                });
+                self.ui_health_grid(ui);
+                if changed {
+                    self.save_data();
+                }
+            });
+    }
+
+    fn ui_health_grid(&self, ui: &mut Ui) {
+        let now = Local::now().timestamp();
+        let metrics = self.metrics();
+        egui::Grid::new("health_grid")
+            .striped(true)
+            .spacing([16.0, 4.0])
+            .show(ui, |ui| {
+                for title in [
+                    "Schedule",
+                    "Health",
+                    "Last success",
+                    "Last run",
+                    "Duration",
+                    "Copied",
+                    "Destination free",
+                    "Full in",
+                ] {
+                    ui.strong(title);
+                }
+                ui.end_row();
+
+                for s in &self.schedules {
+                    let m = metrics.get(s.id);
+                    let history = m.map(|m| m.history.as_slice()).unwrap_or_default();
+                    let health = Health::assess(s.period_hours, m, &self.settings.health, now);
+                    ui.label(s.display_name());
+                    ui.colored_label(health.color(), format!("● {}", health.label()));
+                    ui.label(m.and_then(|m| m.last_success).map_or_else(
+                        || "Never".to_owned(),
+                        |t| format!("{} ago", health::format_duration(now - t)),
+                    ));
+                    ui.label(match m.and_then(|m| m.last_run.map(|t| (t, m))) {
+                        Some((t, m)) => format!(
+                            "{} ago, {}",
+                            health::format_duration(now - t),
+                            if m.last_success == Some(t) {
+                                "succeeded"
+                            } else {
+                                "with errors"
+                            }
+                        ),
+                        None => "Never".to_owned(),
+                    });
+                    ui.horizontal(|ui| {
+                        let values: Vec<f64> = history.iter().map(|p| p.duration_secs).collect();
+                        health::sparkline(ui, &values)
+                            .on_hover_text(format!("Last {} run(s)", values.len()));
+                        if let Some(last) = history.last() {
+                            ui.label(health::format_duration(last.duration_secs.round() as i64));
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let values: Vec<f64> =
+                            history.iter().map(|p| p.bytes_copied as f64).collect();
+                        health::sparkline(ui, &values)
+                            .on_hover_text(format!("Last {} run(s)", values.len()));
+                        if let Some(last) = history.last() {
+                            ui.label(health::format_bytes(last.bytes_copied));
+                        }
+                    });
+                    ui.label(match self.free_space.get(&s.id) {
+                        Some(&bytes) => health::format_bytes(bytes),
+                        None if s.dest_kind.is_remote() => "n/a (remote)".to_owned(),
+                        None => "Unknown".to_owned(),
+                    });
+                    match health::days_until_full(history) {
+                        Some(days) => {
+                            let color = if days < health::FULL_SOON_DAYS {
+                                Health::Red.color()
+                            } else {
+                                ui.visuals().text_color()
+                            };
+                            ui.colored_label(color, health::format_days(days))
+                                .on_hover_text("Forecast from the free space after recent runs");
+                        }
+                        None => {
+                            ui.label("-").on_hover_text(
+                                "Needs a day of runs with shrinking free space at a local destination",
+                            );
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// Warn when the destination of `idx` is forecast to be full soon.
+    fn warn_if_filling_up(&mut self, idx: usize) {
+        let Some(s) = self.schedules.get(idx) else {
+            return;
+        };
+        let days = self
+            .metrics()
+            .get(s.id)
+            .and_then(|m| health::days_until_full(&m.history));
+        if let Some(days) = days.filter(|&d| d < health::FULL_SOON_DAYS) {
+            let msg = format!(
+                "{} is filling up: full in {} at the current rate",
+                s.dest_label(),
+                health::format_days(days)
+            );
+            self.log_at(Level::Warn, None, msg);
+        }
+    }
+
+    fn ui_queue(&mut self, ui: &mut Ui) {
+        let title = format!(
+            "Queue ({} running, {} waiting)",
+            self.queue.running.len(),
+            self.queue.waiting.len()
+        );
+        egui::CollapsingHeader::new(title)
+            .id_source("queue")
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Max concurrent backups");
+                    if ui
+                        .add(
+                            egui::DragValue::new(&mut self.settings.max_concurrent)
+                                .clamp_range(1..=16),
+                        )
+                        .changed()
+                    {
+                        self.save_data();
+                    }
+                });
+                for running in &self.queue.running {
+                    let name = self
+                        .schedules
+                        .get(running.job.schedule)
+                        .map(Schedule::display_name)
+                        .unwrap_or_default();
+                    ui.horizontal(|ui| {
+                        ui.colored_label(Color32::YELLOW, "Running");
+                        ui.separator();
+                        ui.label(name);
+                        ui.separator();
+                        ui.label(running.job.kind.label());
+                        ui.separator();
+                        ui.label(format!("since {}", running.started_at.format("%H:%M:%S")));
+                    });
+                }
+                let mut remove = None;
+                for (pos, job) in self.queue.waiting.iter().enumerate() {
+                    let name = self
+                        .schedules
+                        .get(job.schedule)
+                        .map(Schedule::display_name)
+                        .unwrap_or_default();
+                    ui.horizontal(|ui| {
+                        ui.colored_label(Color32::LIGHT_BLUE, job.priority.label());
+                        ui.separator();
+                        ui.label(name);
+                        ui.separator();
+                        ui.label(job.kind.label());
+                        ui.separator();
+                        ui.label(format!("queued {}", job.queued_at.format("%H:%M:%S")));
+                        if let Some(reason) = self.queue.blocked_by(job) {
+                            ui.separator();
+                            ui.label(reason);
+                        }
+                        if ui.button("Remove").clicked() {
+                            remove = Some(pos);
+                        }
+                    });
+                }
+                // A removed full backup counts as this period's run, or the
+                // timer would queue it again right away.
+                if let Some(job) = remove.and_then(|pos| self.queue.remove_waiting(pos))
+                    && job.kind == JobKind::Full
+                    && let Some(s) = self.schedules.get_mut(job.schedule)
+                {
+                    s.last_time = Local::now().naive_local();
+                }
+            });
+    }
+
+    fn ui_email(&mut self, ui: &mut Ui) {
+        egui::CollapsingHeader::new("Email alerts")
+            .id_source("email")
+            .show(ui, |ui| {
+                let email = &mut self.settings.email;
+                let mut changed = false;
+                ui.horizontal(|ui| {
+                    ui.label("SMTP server");
+                    changed |= ui
+                        .add(
+                            TextEdit::singleline(&mut email.host)
+                                .hint_text("smtp.example.com")
+                                .desired_width(160.0),
+                        )
+                        .lost_focus();
+                    ui.label("Port");
+                    let port = ui.add(egui::DragValue::new(&mut email.port).clamp_range(1..=65535));
+                    changed |= port.drag_stopped() || port.lost_focus();
+                    egui::ComboBox::from_id_source("email_security")
+                        .selected_text(email.security.label())
+                        .show_ui(ui, |ui| {
+                            for security in Security::ALL {
+                                changed |= ui
+                                    .selectable_value(
+                                        &mut email.security,
+                                        security,
+                                        security.label(),
+                                    )
+                                    .changed();
+                            }
+                        });
+                    ui.label("User");
+                    changed |= ui
+                        .add(TextEdit::singleline(&mut email.user).desired_width(120.0))
+                        .lost_focus();
+                    ui.label("Password");
+                    changed |= ui
+                        .add(
+                            TextEdit::singleline(&mut email.password)
+                                .password(true)
+                                .desired_width(120.0),
+                        )
+                        .lost_focus();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("From");
+                    changed |= ui
+                        .add(
+                            TextEdit::singleline(&mut email.from)
+                                .hint_text("backup@example.com")
+                                .desired_width(180.0),
+                        )
+                        .lost_focus();
+                    ui.label("To");
+                    changed |= ui
+                        .add(
+                            TextEdit::singleline(&mut email.to)
+                                .hint_text("admin@example.com, ops@example.com")
+                                .desired_width(260.0),
+                        )
+                        .lost_focus();
+                });
+                ui.horizontal(|ui| {
+                    changed |= ui
+                        .checkbox(&mut email.alert_on_failure, "Mail failed backups")
+                        .changed();
+                    changed |= ui
+                        .checkbox(&mut email.daily_digest, "Daily summary at")
+                        .changed();
+                    let hour = ui.add(
+                        egui::DragValue::new(&mut email.digest_hour)
+                            .clamp_range(0..=23)
+                            .suffix(":00"),
+                    );
+                    changed |= hour.drag_stopped() || hour.lost_focus();
+                });
+                let test = ui.button("Send test email").clicked();
+                if changed {
+                    self.save_data();
+                }
+                if test {
+                    if self.settings.email.is_configured() {
+                        self.send_email(
+                            "AutoBackup test email".to_owned(),
+                            "Email alerts from AutoBackup are working.\n".to_owned(),
+                            None,
+                        );
+                    } else {
+                        self.log("Email needs a server, a sender and a recipient");
+                    }
+                }
+            });
+    }
+
+    fn ui_webhook(&mut self, ui: &mut Ui) {
+        egui::CollapsingHeader::new("Webhook")
+            .id_source("webhook")
+            .show(ui, |ui| {
+                let hook = &mut self.settings.webhook;
+                let mut changed = false;
+                ui.horizontal(|ui| {
+                    ui.label("URL");
+                    changed |= ui
+                        .add(
+                            TextEdit::singleline(&mut hook.url)
+                                .hint_text("https://chat.example.com/hooks/backup")
+                                .desired_width(360.0),
+                        )
+                        .lost_focus();
+                    ui.label("Header");
+                    changed |= ui
+                        .add(
+                            TextEdit::singleline(&mut hook.header)
+                                .hint_text("Authorization: Bearer ...")
+                                .desired_width(220.0),
+                        )
+                        .lost_focus();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Post on");
+                    changed |= ui.checkbox(&mut hook.on_start, "start").changed();
+                    changed |= ui.checkbox(&mut hook.on_success, "success").changed();
+                    changed |= ui
+                        .checkbox(&mut hook.on_failure, "failure or errors")
+                        .changed();
+                });
+                let test = ui.button("Send test event").clicked();
+                if changed {
+                    self.save_data();
+                }
+                if test {
+                    if self.settings.webhook.is_configured() {
+                        self.post_webhook(serde_json::json!({
+                            "event": "test",
+                            "sent_at": Local::now().to_rfc3339(),
+                        }));
+                    } else {
+                        self.log("Webhook URL is empty");
+                    }
+                }
+            });
+    }
+
+    fn ui_metrics(&mut self, ui: &mut Ui) {
+        egui::CollapsingHeader::new("Metrics")
+            .id_source("metrics")
+            .show(ui, |ui| {
+                let settings = &mut self.settings.metrics;
+                let mut restart = false;
+                ui.horizontal(|ui| {
+                    restart |= ui
+                        .checkbox(&mut settings.enabled, "Serve Prometheus metrics at")
+                        .changed();
+                    restart |= ui
+                        .add(
+                            TextEdit::singleline(&mut settings.address)
+                                .hint_text("127.0.0.1:9184")
+                                .desired_width(160.0),
+                        )
+                        .lost_focus();
+                    ui.label("/metrics");
+                });
+                if restart {
+                    self.save_data();
+                    self.restart_metrics_server();
+                }
+            });
+    }
+
+    fn ui_api(&mut self, ui: &mut Ui) {
+        egui::CollapsingHeader::new("Control API")
+            .id_source("api")
+            .show(ui, |ui| {
+                let settings = &mut self.settings.api;
+                let mut restart = false;
+                let mut new_token = false;
+                ui.horizontal(|ui| {
+                    restart |= ui
+                        .checkbox(
+                            &mut settings.enabled,
+                            "Serve the control API on 127.0.0.1 port",
+                        )
+                        .changed();
+                    let port =
+                        ui.add(egui::DragValue::new(&mut settings.port).clamp_range(1024..=65535));
+                    restart |= port.drag_stopped() || port.lost_focus();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Send the token as \"Authorization: Bearer <token>\"");
+                    if ui
+                        .add_enabled(!settings.token.is_empty(), Button::new("Copy token"))
+                        .clicked()
+                    {
+                        let token = settings.token.clone();
+                        ui.output_mut(|o| o.copied_text = token);
+                    }
+                    new_token = ui.button("New token").clicked();
+                });
+                if new_token {
+                    // Regenerated on restart, and old clients are locked out.
+                    settings.token.clear();
+                    restart = true;
+                }
+                if restart {
+                    self.save_data();
+                    self.restart_api_server();
+                }
+            });
+    }
+
     fn ui_logs(&mut self, ui: &mut Ui) {
-        ui.heading("Logs");
-        egui::ScrollArea::vertical()
+        let mut copy = false;
+        let mut export = false;
+        ui.horizontal(|ui| {
+            ui.heading("Logs");
+            egui::ComboBox::from_id_source("log_level")
+                .selected_text(self.log_min_level.filter_label())
+                .show_ui(ui, |ui| {
+                    for level in Level::ALL {
+                        ui.selectable_value(&mut self.log_min_level, level, level.filter_label());
+                    }
+                });
+            let schedule_name = |id: Option<u64>| {
+                id.and_then(|id| self.schedules.iter().find(|s| s.id == id))
+                    .map_or_else(|| "All schedules".to_owned(), Schedule::display_name)
+            };
+            egui::ComboBox::from_id_source("log_schedule")
+                .selected_text(schedule_name(self.log_schedule))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.log_schedule, None, "All schedules");
+                    for s in &self.schedules {
+                        ui.selectable_value(&mut self.log_schedule, Some(s.id), s.display_name());
+                    }
+                });
+            ui.add(
+                TextEdit::singleline(&mut self.log_search)
+                    .hint_text("Search")
+                    .desired_width(200.0),
+            );
+            copy = ui.button("Copy").clicked();
+            export = ui.button("Export...").clicked();
+        });
+
+        let search = self.log_search.trim().to_lowercase();
+        let visible: Vec<&LogRecord> = self
+            .logs
+            .iter()
+            .filter(|r| {
+                r.level >= self.log_min_level
+                    && self.log_schedule.is_none_or(|id| r.schedule == Some(id))
+                    && (search.is_empty() || r.message.to_lowercase().contains(&search))
+            })
+            .collect();
+
+        if copy || export {
+            let text: String = visible.iter().map(|r| r.line() + "\n").collect();
+            if copy {
+                ui.output_mut(|o| o.copied_text = text);
+            } else if let Some(path) = FileDialog::new()
+                .set_file_name("AutoBackup-log.txt")
+                .save_file()
+            {
+                let (level, msg) = match fs::write(&path, text) {
+                    Ok(()) => (
+                        Level::Info,
+                        format!(
+                            "Exported {} log line(s) to {}",
+                            visible.len(),
+                            path.display()
+                        ),
+                    ),
+                    Err(e) => (Level::Error, format!("Failed to export logs: {e}")),
+                };
+                self.log_at(level, None, msg);
+                return;
+            }
+        }
+
+        // Only the rows in view are laid out, so long logs stay responsive.
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::both()
             .stick_to_bottom(true)
-            .show(ui, |ui| {
-                for line in &self.logs {
-                    ui.label(line);
+            .auto_shrink([false, false])
+            .show_rows(ui, row_height, visible.len(), |ui, rows| {
+                for record in &visible[rows] {
+                    let text = RichText::new(record.line());
+                    let text = match record.level {
+                        Level::Info => text,
+                        Level::Warn => text.color(Color32::YELLOW),
+                        Level::Error => text.color(Color32::LIGHT_RED),
+                    };
+                    // Rows must keep the same height for show_rows.
+                    ui.add(egui::Label::new(text).wrap(false));
                 }
             });
     }
@@ -358,45 +1469,16 @@ impl AppState {
     }
 
     fn action_add(&mut self) {
-        let period = self
-            .input_period_hours
-            .trim()
-            .parse::<i32>()
-            .ok()
-            .filter(|&p| p >= 1)
-            .unwrap_or(24);
-
-        if self.input_source_dir.trim().is_empty() {
-            self.log("Source folder is empty");
-            return;
-        }
-        if !Path::new(&self.input_source_dir).exists() {
-            self.log("Source folder does not exist");
-            return;
-        }
-        if self.input_dest_dir.trim().is_empty() {
-            self.log("Destination folder is empty");
-            return;
-        }
-        if !Path::new(&self.input_dest_dir).exists() {
-            if let Err(e) = fs::create_dir_all(&self.input_dest_dir) {
-                self.log(format!("Failed to create destination: {e}"));
-                return;
+        let added = self
+            .schedule_from_inputs()
+            .and_then(|sched| self.add_schedule(sched));
+        match added {
+            Ok(idx) => {
+                self.selected_index = Some(idx);
+                self.clear_inputs();
             }
+            Err(e) => self.log(e),
         }
-
-        let sched = Schedule::new(
-            self.input_source_dir.clone(),
-            self.input_dest_dir.clone(),
-            period,
-            self.label_skip_files.clone(),
-            self.label_skip_folders.clone(),
-            self.input_use_zip,
-        );
-        self.schedules.push(sched);
-        self.selected_index = Some(self.schedules.len() - 1);
-        self.save_data();
-        self.clear_inputs();
     }
 
     fn action_edit(&mut self) {
@@ -404,6 +1486,18 @@ impl AppState {
             self.log("Select a row to edit");
             return;
         };
+        let edited = self
+            .schedule_from_inputs()
+            .and_then(|sched| self.replace_schedule(idx, sched));
+        match edited {
+            Ok(()) => self.clear_inputs(),
+            Err(e) => self.log(e),
+        }
+    }
+
+    /// A schedule built from the input fields, not yet checked by
+    /// `check_schedule`.
+    fn schedule_from_inputs(&self) -> Result<Schedule, String> {
         let period = self
             .input_period_hours
             .trim()
@@ -411,32 +1505,148 @@ impl AppState {
             .ok()
             .filter(|&p| p >= 1)
             .unwrap_or(24);
+        let profiles = throttle::parse_profiles(&self.input_rate_profiles)?;
 
-        if self.input_source_dir.trim().is_empty() || !Path::new(&self.input_source_dir).exists() {
-            self.log("Invalid source folder");
-            return;
+        let mut sched = Schedule::new(
+            self.input_source_dir.clone(),
+            self.input_dest_dir.clone(),
+            period,
+            self.label_skip_files.clone(),
+            self.label_skip_folders.clone(),
+            self.input_use_zip,
+        );
+        sched.dest_kind = self.input_dest_kind;
+        sched.space_check = self.input_space_check;
+        sched.sftp = self.input_sftp.clone();
+        sched.s3 = self.input_s3.clone();
+        sched.webdav = self.input_webdav.clone();
+        sched.mirror = self.input_mirror;
+        sched.watch = self.input_watch;
+        sched.watch_debounce_secs = self.input_watch_debounce_secs;
+        sched.symlinks = self.input_symlinks;
+        sched.snapshot = self.input_snapshot;
+        sched.priority = self.input_priority;
+        sched.depends_on = self.input_depends_on.map(|schedule| Dependency {
+            schedule,
+            when: self.input_trigger,
+        });
+        sched.retries = self.input_retries;
+        sched.retry_delay_secs = self.input_retry_delay_secs;
+        sched.name = self.input_name.trim().to_owned();
+        sched.hooks = self.input_hooks.clone();
+        sched.notifications = self.input_notifications.clone();
+        sched.throttle = Throttle {
+            profiles,
+            ..self.input_throttle.clone()
+        };
+        Ok(sched)
+    }
+
+    /// Validate `sched` before it is added or replaces the schedule with the
+    /// same id, creating a missing local destination folder.
+    fn check_schedule(&self, sched: &Schedule) -> Result<(), String> {
+        if sched.source_dir.trim().is_empty() {
+            return Err("Source folder is empty".to_owned());
         }
-        if self.input_dest_dir.trim().is_empty() {
-            self.log("Invalid destination folder");
-            return;
+        if !Path::new(&sched.source_dir).exists() {
+            return Err("Source folder does not exist".to_owned());
         }
-        if !Path::new(&self.input_dest_dir).exists() {
-            if let Err(e) = fs::create_dir_all(&self.input_dest_dir) {
-                self.log(format!("Failed to create destination: {e}"));
-                return;
+        if sched.period_hours < 1 {
+            return Err("Period must be at least one hour".to_owned());
+        }
+        if let Some(dep) = sched.depends_on {
+            self.check_dependency(sched.id, dep.schedule)?;
+        }
+        if sched.dest_kind.is_remote() {
+            if let Some(err) = sched.remote_error() {
+                return Err(err.to_owned());
+            }
+        } else {
+            if sched.dest_dir.trim().is_empty() {
+                return Err("Destination folder is empty".to_owned());
+            }
+            if !Path::new(&sched.dest_dir).exists()
+                && let Err(e) = fs::create_dir_all(&sched.dest_dir)
+            {
+                return Err(format!("Failed to create destination: {e}"));
             }
         }
+        Ok(())
+    }
 
-        let s = &mut self.schedules[idx];
-        s.source_dir = self.input_source_dir.clone();
-        s.dest_dir = self.input_dest_dir.clone();
-        s.period_hours = period;
-        s.skip_file_exts_label = self.label_skip_files.clone();
-        s.skip_folders_label = self.label_skip_folders.clone();
-        s.use_zip = self.input_use_zip;
+    /// Whether the schedule with id `own` may run after `upstream`.
+    fn check_dependency(&self, own: u64, upstream: u64) -> Result<(), String> {
+        if !self.schedules.iter().any(|s| s.id == upstream) {
+            return Err("The schedule to run after no longer exists".to_owned());
+        }
+        // Follow the chain upwards; reaching ourselves means a loop.
+        let mut next = Some(upstream);
+        for _ in 0..=self.schedules.len() {
+            let Some(id) = next else {
+                break;
+            };
+            if id == own {
+                return Err(
+                    "A schedule cannot run after itself, directly or through others".to_owned(),
+                );
+            }
+            next = self
+                .schedules
+                .iter()
+                .find(|s| s.id == id)
+                .and_then(|s| s.depends_on)
+                .map(|d| d.schedule);
+        }
+        Ok(())
+    }
+
+    /// Add `sched` under a new id, returning its index.
+    fn add_schedule(&mut self, mut sched: Schedule) -> Result<usize, String> {
+        sched.id = self.next_schedule_id();
+        self.check_schedule(&sched)?;
+        self.schedules.push(sched);
+        self.schedules_changed();
+        Ok(self.schedules.len() - 1)
+    }
 
+    /// Replace the schedule at `idx`, keeping its id and run state.
+    fn replace_schedule(&mut self, idx: usize, mut sched: Schedule) -> Result<(), String> {
+        let Some(old) = self.schedules.get(idx) else {
+            return Err("No such schedule".to_owned());
+        };
+        sched.id = old.id;
+        sched.last_time = old.last_time;
+        sched.is_running = old.is_running;
+        self.check_schedule(&sched)?;
+        self.schedules[idx] = sched;
+        self.schedules_changed();
+        Ok(())
+    }
+
+    /// Persist the schedules and bring everything derived from them up to date.
+    fn schedules_changed(&mut self) {
         self.save_data();
-        self.clear_inputs();
+        self.restart_watchers();
+        self.metrics().sync(&self.schedules);
+        self.free_space_checked = None;
+    }
+
+    fn next_schedule_id(&self) -> u64 {
+        self.schedules.iter().map(|s| s.id).max().unwrap_or(0) + 1
+    }
+
+    /// Give schedules saved before ids existed (or duplicated by hand) an id
+    /// of their own.
+    fn assign_schedule_ids(&mut self) {
+        let mut seen = HashSet::new();
+        for idx in 0..self.schedules.len() {
+            let id = self.schedules[idx].id;
+            if id == 0 || !seen.insert(id) {
+                let id = self.next_schedule_id();
+                self.schedules[idx].id = id;
+                seen.insert(id);
+            }
+        }
     }
 
     fn action_delete(&mut self) {
@@ -444,26 +1654,221 @@ impl AppState {
             self.log("Select a row to delete");
             return;
         };
-        if idx < self.schedules.len() {
-            self.schedules.remove(idx);
-            self.selected_index = None;
-            self.save_data();
+        if let Err(e) = self.remove_schedule(idx) {
+            self.log(e);
         }
     }
 
+    fn remove_schedule(&mut self, idx: usize) -> Result<(), String> {
+        // Running backups report back by index, which deleting would shift.
+        if !self.queue.running.is_empty() {
+            return Err("Wait for running backups to finish before deleting".to_owned());
+        }
+        if idx >= self.schedules.len() {
+            return Err("No such schedule".to_owned());
+        }
+        let removed = self.schedules.remove(idx);
+        self.queue.remove_schedule(idx);
+        let mut unchained = Vec::new();
+        for s in &mut self.schedules {
+            if s.depends_on.is_some_and(|d| d.schedule == removed.id) {
+                s.depends_on = None;
+                unchained.push(s.display_name());
+            }
+        }
+        for name in unchained {
+            self.log(format!(
+                "{name} no longer runs after {}",
+                removed.display_name()
+            ));
+        }
+        self.selected_index = match self.selected_index {
+            Some(sel) if sel > idx => Some(sel - 1),
+            Some(sel) if sel == idx => None,
+            sel => sel,
+        };
+        self.schedules_changed();
+        Ok(())
+    }
+
     fn action_run_now(&mut self) {
         let Some(idx) = self.selected_index else {
             self.log("Select a row to run");
             return;
         };
+        if let Err(e) = self.run_now(idx) {
+            self.log(e);
+        }
+    }
+
+    fn run_now(&mut self, idx: usize) -> Result<(), String> {
         if idx >= self.schedules.len() {
+            return Err("No such schedule".to_owned());
+        }
+        if self.queue.is_waiting_full(idx) {
+            return Err("Backup already queued".to_owned());
+        }
+        self.enqueue(idx, JobKind::Full);
+        self.log(format!(
+            "Backup queued: {}",
+            self.schedules[idx].display_name()
+        ));
+        Ok(())
+    }
+
+    /// Queue the schedules chained after `idx` whose trigger matches `ok`.
+    fn queue_dependents(&mut self, idx: usize, ok: bool) {
+        let Some(upstream) = self.schedules.get(idx) else {
+            return;
+        };
+        let (id, name) = (upstream.id, upstream.display_name());
+        let dependents: Vec<usize> = (0..self.schedules.len())
+            .filter(|&i| {
+                self.schedules[i]
+                    .depends_on
+                    .is_some_and(|d| d.schedule == id && d.when.matches(ok))
+            })
+            .collect();
+        for i in dependents {
+            self.enqueue(i, JobKind::Full);
+            let msg = format!(
+                "Backup queued: {} (after {name} {})",
+                self.schedules[i].display_name(),
+                if ok { "succeeded" } else { "failed" }
+            );
+            self.log(msg);
+        }
+    }
+
+    fn action_last_report(&mut self) {
+        let Some(s) = self.selected_index.and_then(|idx| self.schedules.get(idx)) else {
+            self.log("Select a row to show its last report");
+            return;
+        };
+        let name = s.display_name();
+        match report::last_report_path(s.id).map(fs::read_to_string) {
+            Some(Ok(text)) => self.report_view = Some((format!("Last report: {name}"), text)),
+            _ => self.log(format!("No report yet for {name}")),
+        }
+    }
+
+    fn ui_report(&mut self, ctx: &Context) {
+        let Some((title, text)) = &self.report_view else {
             return;
+        };
+        let mut open = true;
+        egui::Window::new(title.as_str())
+            .id(egui::Id::new("report_view"))
+            .open(&mut open)
+            .default_size(egui::vec2(800.0, 500.0))
+            .show(ctx, |ui| {
+                egui::ScrollArea::both().show(ui, |ui| {
+                    // A `&str` buffer keeps the text selectable but read-only.
+                    ui.add(
+                        TextEdit::multiline(&mut text.as_str())
+                            .code_editor()
+                            .desired_width(f32::INFINITY),
+                    );
+                });
+            });
+        if !open {
+            self.report_view = None;
         }
-        if self.schedules[idx].is_running {
-            self.log("Backup already running");
+    }
+
+    /// Record a finished run for the digest and mail an alert if it failed.
+    fn mail_outcome(&mut self, idx: usize, outcome: Outcome) {
+        let email = &self.settings.email;
+        let Some(s) = self.schedules.get(idx) else {
             return;
+        };
+        let name = s.display_name();
+        let alert = (email.alert_on_failure && email.is_configured() && !outcome.is_ok())
+            .then(|| {
+                (
+                    format!("AutoBackup: backup failed: {name}"),
+                    format!(
+                        "The backup of {} to {} failed at {}.\nSee the AutoBackup log for details.\n",
+                        s.source_dir,
+                        s.dest_label(),
+                        Local::now().format("%Y-%m-%d %H:%M:%S")
+                    ),
+                )
+            });
+        if email.daily_digest
+            && let Err(e) = self.digest.record(name, outcome)
+        {
+            self.log_at(
+                Level::Warn,
+                None,
+                format!("Failed to save the email digest: {e}"),
+            );
+        }
+        if let Some((subject, body)) = alert {
+            self.send_email(subject, body, None);
+        }
+    }
+
+    fn mail_digest_if_due(&mut self) {
+        let email = &self.settings.email;
+        if !email.daily_digest || !email.is_configured() {
+            return;
+        }
+        match self
+            .digest
+            .take_due(email.digest_hour, Local::now().naive_local())
+        {
+            Ok(Some((subject, body, batch))) => self.send_email(subject, body, Some(batch)),
+            Ok(None) => {}
+            Err(e) => self.log_at(
+                Level::Warn,
+                None,
+                format!("Failed to save the email digest: {e}"),
+            ),
         }
-        self.spawn_backup(idx);
+    }
+
+    /// POST to the webhook in the background, logging the result.
+    fn post_webhook(&self, payload: serde_json::Value) {
+        let hook = self.settings.webhook.clone();
+        let tx = self.tx.clone();
+        std::thread::spawn(move || {
+            let event = payload["event"].as_str().unwrap_or_default();
+            let msg = match hook.post(&payload) {
+                Ok(()) => AppMsg::Log(Level::Info, format!("Posted {event} to the webhook")),
+                Err(e) => AppMsg::Log(
+                    Level::Error,
+                    format!("Failed to post {event} to the webhook: {e:#}"),
+                ),
+            };
+            let _ = tx.send(msg);
+        });
+    }
+
+    /// Send a mail in the background, logging the result and reporting it
+    /// back when it is the digest for `batch`.
+    fn send_email(&self, subject: String, body: String, batch: Option<DigestBatch>) {
+        let email = self.settings.email.clone();
+        let tx = self.tx.clone();
+        std::thread::spawn(move || {
+            let sent = email.send(&subject, &body);
+            let msg = match &sent {
+                Ok(()) => AppMsg::Log(Level::Info, format!("Sent email \"{subject}\"")),
+                Err(e) => AppMsg::Log(
+                    Level::Error,
+                    format!("Failed to send email \"{subject}\": {e:#}"),
+                ),
+            };
+            let _ = tx.send(msg);
+            if let Some(batch) = batch {
+                let _ = tx.send(AppMsg::DigestSent(batch, sent.is_ok()));
+            }
+        });
+    }
+
+    fn enqueue(&mut self, idx: usize, kind: JobKind) {
+        let s = &self.schedules[idx];
+        self.queue.push(idx, kind, s.priority, s.dest_path());
     }
 
     fn fill_inputs_from(&mut self, idx: usize) {
@@ -477,6 +1882,26 @@ impl AppState {
         self.label_skip_files = s.skip_file_exts_label.clone();
         self.label_skip_folders = s.skip_folders_label.clone();
         self.input_use_zip = s.use_zip;
+        self.input_dest_kind = s.dest_kind;
+        self.input_space_check = s.space_check;
+        self.input_sftp = s.sftp.clone();
+        self.input_s3 = s.s3.clone();
+        self.input_webdav = s.webdav.clone();
+        self.input_mirror = s.mirror;
+        self.input_watch = s.watch;
+        self.input_watch_debounce_secs = s.watch_debounce_secs;
+        self.input_symlinks = s.symlinks;
+        self.input_snapshot = s.snapshot;
+        self.input_priority = s.priority;
+        self.input_depends_on = s.depends_on.map(|d| d.schedule);
+        self.input_trigger = s.depends_on.map(|d| d.when).unwrap_or_default();
+        self.input_retries = s.retries;
+        self.input_retry_delay_secs = s.retry_delay_secs;
+        self.input_name = s.name.clone();
+        self.input_hooks = s.hooks.clone();
+        self.input_notifications = s.notifications.clone();
+        self.input_throttle = s.throttle.clone();
+        self.input_rate_profiles = s.throttle.profiles_label();
     }
 
     fn clear_inputs(&mut self) {
@@ -488,55 +1913,169 @@ impl AppState {
         self.label_skip_files.clear();
         self.label_skip_folders.clear();
         self.input_use_zip = false;
+        self.input_dest_kind = DestKind::Local;
+        self.input_space_check = SpaceCheck::default();
+        self.input_sftp = SftpConfig::default();
+        self.input_s3 = S3Config::default();
+        self.input_webdav = WebDavConfig::default();
+        self.input_mirror = false;
+        self.input_watch = false;
+        self.input_watch_debounce_secs = 5;
+        self.input_symlinks = SymlinkPolicy::default();
+        self.input_snapshot = SnapshotMode::default();
+        self.input_priority = Priority::default();
+        self.input_depends_on = None;
+        self.input_trigger = Trigger::default();
+        self.input_retries = 3;
+        self.input_retry_delay_secs = 2;
+        self.input_name.clear();
+        self.input_hooks = Hooks::default();
+        self.input_notifications = Notifications::default();
+        self.input_throttle = Throttle::default();
+        self.input_rate_profiles.clear();
     }
 
     fn log<T: Into<String>>(&mut self, msg: T) {
-        let line = format!(
-            "[{}] {}",
-            Local::now().format("%Y-%m-%d %H:%M:%S"),
-            msg.into()
-        );
-        self.logs.push(line);
+        self.log_at(Level::Info, None, msg);
+    }
+
+    fn log_at<T: Into<String>>(&mut self, level: Level, tag: Option<RunTag>, msg: T) {
+        let record = LogRecord::new(level, tag, msg.into());
+        if let Some(file) = &mut self.log_file
+            && let Err(e) = file.append(&record)
+        {
+            // Stop trying after the first failure instead of failing every line.
+            self.log_file = None;
+            self.logs.push(LogRecord::new(
+                Level::Error,
+                None,
+                format!("Failed to write the log file, logging to the window only: {e}"),
+            ));
+        }
+        self.logs.push(record);
+        if self.logs.len() > logfile::MEMORY_RECORDS {
+            let excess = self.logs.len() - logfile::MEMORY_RECORDS;
+            self.logs.drain(..excess);
+        }
     }
 
     fn tick(&mut self) {
         // receive async messages
         while let Ok(msg) = self.rx.try_recv() {
             match msg {
-                AppMsg::Log(s) => self.log(s),
-                AppMsg::BackupFinished(idx, ok) => {
+                AppMsg::Log(level, s) => self.log_at(level, None, s),
+                AppMsg::RunLog(tag, level, s) => self.log_at(level, Some(tag), s),
+                AppMsg::BackupFinished(idx, summary) => {
+                    self.queue.finish(idx);
                     if let Some(s) = self.schedules.get_mut(idx) {
                         s.is_running = false;
                         s.last_time = Local::now().naive_local();
+                        let id = s.id;
+                        let saved = self.metrics().record(id, &summary);
+                        if let Err(e) = saved {
+                            self.log_at(Level::Warn, None, format!("Failed to save metrics: {e}"));
+                        }
+                        self.warn_if_filling_up(idx);
                     }
-                    if ok {
-                        self.log("Backup completed");
-                    } else {
-                        self.log("Backup failed");
+                    self.mail_outcome(idx, summary.outcome);
+                    if self.settings.webhook.wants(summary.outcome)
+                        && let Some(s) = self.schedules.get(idx)
+                    {
+                        self.post_webhook(webhook::finished_payload(s, &summary));
+                    }
+                    self.queue_dependents(idx, summary.outcome.is_ok());
+                }
+                AppMsg::SourceChanged(id, paths) => {
+                    let pending = self.pending_changes.entry(id).or_insert(PendingChanges {
+                        paths: HashSet::new(),
+                        last_event: Instant::now(),
+                    });
+                    pending.paths.extend(paths);
+                    pending.last_event = Instant::now();
+                }
+                AppMsg::Api(call, reply) => {
+                    let _ = reply.send(self.handle_api(call));
+                }
+                AppMsg::DigestSent(batch, true) => {
+                    if let Err(e) = self.digest.sent(batch) {
+                        self.log_at(
+                            Level::Warn,
+                            None,
+                            format!("Failed to save the email digest: {e}"),
+                        );
+                    }
+                }
+                AppMsg::DigestSent(_, false) => {
+                    self.digest.send_failed(Local::now().naive_local());
+                }
+                AppMsg::FreeSpace(free) => {
+                    self.free_space = free;
+                    self.free_space_checking = false;
+                }
+                AppMsg::ChangesBackedUp(idx) => {
+                    // Watch runs only cover changed files, so the periodic
+                    // full run keeps its own timer.
+                    self.queue.finish(idx);
+                    if let Some(s) = self.schedules.get_mut(idx) {
+                        s.is_running = false;
+                        let id = s.id;
+                        self.metrics().set_running(id, false);
                     }
                 }
             }
         }
 
+        // queue watched changes once their debounce window is quiet
+        let due: Vec<(u64, usize)> = self
+            .pending_changes
+            .iter()
+            .filter_map(|(id, p)| {
+                let idx = self.schedules.iter().position(|s| s.id == *id)?;
+                let quiet = Duration::from_secs(self.schedules[idx].watch_debounce_secs);
+                (p.last_event.elapsed() >= quiet).then_some((*id, idx))
+            })
+            .collect();
+        for (id, idx) in due {
+            if let Some(pending) = self.pending_changes.remove(&id) {
+                self.enqueue(idx, JobKind::Changes(pending.paths.into_iter().collect()));
+            }
+        }
+
         // periodic scan each second
         if self.last_tick.elapsed() >= Duration::from_secs(1) {
             let now = Local::now().naive_local();
             for idx in 0..self.schedules.len() {
                 let run = {
                     let s = &self.schedules[idx];
-                    if s.is_running {
+                    if s.is_running || self.queue.is_waiting_full(idx) {
                         false
                     } else {
-                        let elapsed_hours = ((now - s.last_time).num_seconds() / 3600) as i64;
+                        let elapsed_hours = (now - s.last_time).num_seconds() / 3600;
                         elapsed_hours >= s.period_hours as i64
                     }
                 };
                 if run {
-                    self.spawn_backup(idx);
+                    self.enqueue(idx, JobKind::Full);
                 }
             }
+            self.mail_digest_if_due();
+            if !self.free_space_checking
+                && self
+                    .free_space_checked
+                    .is_none_or(|t| t.elapsed() >= Duration::from_secs(60))
+            {
+                self.refresh_free_space();
+            }
             self.last_tick = Instant::now();
         }
+
+        // start queued jobs while slots are free
+        while let Some(job) = self.queue.next_ready(self.settings.max_concurrent) {
+            match job.kind {
+                JobKind::Full => self.spawn_backup(job.schedule),
+                JobKind::Changes(paths) => self.spawn_changes_backup(job.schedule, paths),
+            }
+        }
     }
 
     fn spawn_backup(&mut self, idx: usize) {
@@ -546,84 +2085,406 @@ impl AppState {
         let mut s = self.schedules[idx].clone();
         s.is_running = true;
         self.schedules[idx].is_running = true;
-        self.log(format!("Backup started: {}", s.source_dir));
+        self.metrics().set_running(s.id, true);
+        let tag = RunTag {
+            schedule: s.id,
+            run: logfile::new_run_id(),
+        };
+        self.log_at(
+            Level::Info,
+            Some(tag),
+            format!("Backup started: {}", s.source_dir),
+        );
+        let hook = &self.settings.webhook;
+        if hook.is_configured() && hook.on_start {
+            self.post_webhook(webhook::started_payload(&s, tag.run, Local::now()));
+        }
 
+        let tx = logfile::tagged_sender(&self.tx, tag);
+        std::thread::spawn(move || {
+            lower_priority_for(&s, &tx);
+            let started = Local::now();
+            let mut report = RunReport::default();
+            let outcome = execute_backup(&s, tx.clone(), &mut report);
+            // Logged from here so the line carries the run's tag.
+            let _ = tx.send(if outcome.is_ok() {
+                AppMsg::Log(Level::Info, "Backup completed".to_owned())
+            } else {
+                AppMsg::Log(Level::Error, "Backup failed".to_owned())
+            });
+            notifications::notify_outcome(&s, outcome, &tx);
+            let mut summary = RunSummary::new(tag.run, outcome, started, &report);
+            if !s.dest_kind.is_remote() {
+                summary.dest_free = destination::free_space(Path::new(&s.dest_dir)).ok();
+            }
+            let _ = tx.send(AppMsg::BackupFinished(idx, summary));
+        });
+    }
+
+    fn spawn_changes_backup(&mut self, idx: usize, paths: Vec<PathBuf>) {
+        if idx >= self.schedules.len() {
+            return;
+        }
+        let s = self.schedules[idx].clone();
+        self.schedules[idx].is_running = true;
+        self.metrics().set_running(s.id, true);
+        let tag = RunTag {
+            schedule: s.id,
+            run: logfile::new_run_id(),
+        };
+        self.log_at(
+            Level::Info,
+            Some(tag),
+            format!(
+                "Backing up {} changed path(s) in {}",
+                paths.len(),
+                s.source_dir
+            ),
+        );
+
+        let tx = logfile::tagged_sender(&self.tx, tag);
+        std::thread::spawn(move || {
+            lower_priority_for(&s, &tx);
+            let ok = execute_changes(&s, &paths, tx.clone());
+            if !ok {
+                let _ = tx.send(AppMsg::Log(
+                    Level::Error,
+                    "Backing up changes failed".to_owned(),
+                ));
+                notifications::notify_outcome(&s, Outcome::Failed, &tx);
+            }
+            let _ = tx.send(AppMsg::ChangesBackedUp(idx));
+        });
+    }
+
+    /// Measure free space at the local destinations in the background; a
+    /// hung mount must not freeze the window.
+    fn refresh_free_space(&mut self) {
+        let dests: Vec<(u64, PathBuf)> = self
+            .schedules
+            .iter()
+            .filter(|s| !s.dest_kind.is_remote())
+            .map(|s| (s.id, PathBuf::from(&s.dest_dir)))
+            .collect();
+        self.free_space_checking = true;
+        self.free_space_checked = Some(Instant::now());
         let tx = self.tx.clone();
         std::thread::spawn(move || {
-            let ok = execute_backup(&s, tx.clone());
-            let _ = tx.send(AppMsg::BackupFinished(idx, ok));
+            let free = dests
+                .into_iter()
+                .filter_map(|(id, dir)| destination::free_space(&dir).ok().map(|bytes| (id, bytes)))
+                .collect();
+            let _ = tx.send(AppMsg::FreeSpace(free));
         });
     }
 
-    fn load_data(&mut self) {
-        if let Some(path) = ini_path() {
-            if Path::new(&path).exists() {
-                if let Ok(file) = File::open(&path) {
-                    let mut reader = BufReader::new(file);
-                    let mut line = String::new();
-                    // first line is title
-                    let _ = reader.read_line(&mut line);
-                    line.clear();
-                    // count line
-                    let _ = reader.read_line(&mut line);
-                    let count: usize = line.trim().parse().unwrap_or(0);
-                    for _ in 0..count {
-                        line.clear();
-                        if reader.read_line(&mut line).is_err() {
-                            break;
-                        }
-                        let parts: Vec<_> =
-                            line.trim_end().split(',').map(|s| s.to_string()).collect();
-                        if parts.len() >= 3 {
-                            let source = parts.get(0).cloned().unwrap_or_default();
-                            let dest = parts.get(1).cloned().unwrap_or_default();
-                            let period = parts
-                                .get(2)
-                                .and_then(|s| s.parse::<i32>().ok())
-                                .unwrap_or(24);
-                            let skip_files = parts.get(3).cloned().unwrap_or_default();
-                            let skip_folders = parts.get(4).cloned().unwrap_or_default();
-                            let use_zip = parts
-                                .get(5)
-                                .and_then(|s| s.parse::<bool>().ok())
-                                .unwrap_or(false);
-                            self.schedules.push(Schedule::new(
-                                source,
-                                dest,
-                                period,
-                                skip_files,
-                                skip_folders,
-                                use_zip,
-                            ));
-                        }
+    fn metrics(&self) -> MutexGuard<'_, Metrics> {
+        self.metrics.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Stop the `/metrics` listener and start it again with the current
+    /// settings, if enabled.
+    fn restart_metrics_server(&mut self) {
+        // Dropped first so a restart on the same address can bind again.
+        self.metrics_server = None;
+        let settings = self.settings.metrics.clone();
+        if !settings.enabled {
+            return;
+        }
+        match metrics::serve(&settings.address, self.metrics.clone()) {
+            Ok(server) => {
+                self.metrics_server = Some(server);
+                self.log(format!(
+                    "Serving metrics at http://{}/metrics",
+                    settings.address.trim()
+                ));
+            }
+            Err(e) => self.log_at(
+                Level::Error,
+                None,
+                format!("Failed to serve metrics at {}: {e}", settings.address),
+            ),
+        }
+    }
+
+    /// Stop the control API and start it again with the current settings, if
+    /// enabled.
+    fn restart_api_server(&mut self) {
+        self.api_server = None;
+        if !self.settings.api.enabled {
+            return;
+        }
+        if self.settings.api.token.is_empty() {
+            match api::new_token() {
+                Ok(token) => {
+                    self.settings.api.token = token;
+                    self.save_data();
+                }
+                Err(e) => {
+                    self.log_at(
+                        Level::Error,
+                        None,
+                        format!("Failed to create a control API token: {e}"),
+                    );
+                    return;
+                }
+            }
+        }
+        let port = self.settings.api.port;
+        match api::serve(&self.settings.api, self.tx.clone(), self.egui_ctx.clone()) {
+            Ok(server) => {
+                self.api_server = Some(server);
+                self.log(format!(
+                    "Control API listening on http://127.0.0.1:{port}/api"
+                ));
+            }
+            Err(e) => self.log_at(
+                Level::Error,
+                None,
+                format!("Failed to start the control API on port {port}: {e}"),
+            ),
+        }
+    }
+
+    /// Carry out a control API call the way the matching button would.
+    fn handle_api(&mut self, call: ApiRequest) -> ApiReply {
+        let index_of = |app: &Self, id: u64| app.schedules.iter().position(|s| s.id == id);
+        let not_found = |id: u64| ApiReply::error(404, format!("No schedule with id {id}"));
+        match call {
+            ApiRequest::List => ApiReply::ok(
+                200,
+                (0..self.schedules.len())
+                    .map(|idx| self.api_schedule(idx))
+                    .collect(),
+            ),
+            ApiRequest::Get(id) => match index_of(self, id) {
+                Some(idx) => ApiReply::ok(200, self.api_schedule(idx)),
+                None => not_found(id),
+            },
+            ApiRequest::Status => {
+                let schedules: Vec<serde_json::Value> = (0..self.schedules.len())
+                    .map(|idx| {
+                        let s = &self.schedules[idx];
+                        serde_json::json!({
+                            "id": s.id,
+                            "name": s.display_name(),
+                            "status": self.api_schedule(idx)["status"],
+                        })
+                    })
+                    .collect();
+                ApiReply::ok(
+                    200,
+                    serde_json::json!({
+                        "running": self.queue.running.len(),
+                        "queued": self.queue.waiting.len(),
+                        "max_concurrent": self.settings.max_concurrent,
+                        "schedules": schedules,
+                    }),
+                )
+            }
+            ApiRequest::Add(value) => {
+                let added = serde_json::from_value::<Schedule>(value)
+                    .map_err(|e| format!("Invalid schedule: {e}"))
+                    .and_then(|sched| self.add_schedule(sched));
+                match added {
+                    Ok(idx) => {
+                        let name = self.schedules[idx].display_name();
+                        self.log(format!("Schedule added through the API: {name}"));
+                        ApiReply::ok(201, self.api_schedule(idx))
                     }
-                    self.log(format!("Loaded {} schedule(s)", self.schedules.len()));
+                    Err(e) => ApiReply::error(400, e),
+                }
+            }
+            ApiRequest::Edit(id, patch) => {
+                let Some(idx) = index_of(self, id) else {
+                    return not_found(id);
+                };
+                let mut value = serde_json::to_value(&self.schedules[idx]).unwrap_or_default();
+                api::merge(&mut value, patch);
+                let edited = serde_json::from_value::<Schedule>(value)
+                    .map_err(|e| format!("Invalid schedule: {e}"))
+                    .and_then(|sched| self.replace_schedule(idx, sched));
+                match edited {
+                    Ok(()) => {
+                        let name = self.schedules[idx].display_name();
+                        self.log(format!("Schedule edited through the API: {name}"));
+                        ApiReply::ok(200, self.api_schedule(idx))
+                    }
+                    Err(e) => ApiReply::error(400, e),
+                }
+            }
+            ApiRequest::Delete(id) => {
+                let Some(idx) = index_of(self, id) else {
+                    return not_found(id);
+                };
+                let name = self.schedules[idx].display_name();
+                match self.remove_schedule(idx) {
+                    Ok(()) => {
+                        self.log(format!("Schedule deleted through the API: {name}"));
+                        ApiReply::ok(200, serde_json::json!({ "deleted": id }))
+                    }
+                    Err(e) => ApiReply::error(409, e),
+                }
+            }
+            ApiRequest::Run(id) => {
+                let Some(idx) = index_of(self, id) else {
+                    return not_found(id);
+                };
+                match self.run_now(idx) {
+                    Ok(()) => ApiReply::ok(202, serde_json::json!({ "queued": id })),
+                    Err(e) => ApiReply::error(409, e),
                 }
             }
         }
     }
 
+    fn api_schedule(&self, idx: usize) -> serde_json::Value {
+        let s = &self.schedules[idx];
+        let last_success = self.metrics().last_success(s.id);
+        api::schedule_json(s, self.queue.is_waiting(idx), last_success)
+    }
+
+    /// Bring the file watchers in line with `schedules` again. Only schedules
+    /// that were added or whose settings changed get a new watcher and lose
+    /// their queued changes; the others keep both.
+    fn restart_watchers(&mut self) {
+        let ids: HashSet<u64> = self.schedules.iter().map(|s| s.id).collect();
+        self.watchers.retain(|id, _| ids.contains(id));
+        self.pending_changes.retain(|id, _| ids.contains(id));
+        for idx in 0..self.schedules.len() {
+            let s = &self.schedules[idx];
+            let (id, settings) = (s.id, serde_json::to_value(s).unwrap_or_default());
+            if self
+                .watchers
+                .get(&id)
+                .is_some_and(|w| w.settings == settings)
+            {
+                continue;
+            }
+            // Stop the old watcher before a new one starts on the same source.
+            self.watchers.remove(&id);
+            self.pending_changes.remove(&id);
+            let watcher = if !s.watch {
+                None
+            } else if s.use_zip && s.dest_kind.is_remote() {
+                self.log(format!(
+                    "Watch ignored for {}: archive uploads run on the period only",
+                    s.source_dir
+                ));
+                None
+            } else {
+                match watch::start(s, self.tx.clone()) {
+                    Ok(w) => Some(w),
+                    Err(e) => {
+                        let msg = format!("Failed to watch {}: {e}", s.source_dir);
+                        self.log_at(Level::Error, None, msg);
+                        None
+                    }
+                }
+            };
+            self.watchers.insert(
+                id,
+                Watched {
+                    settings,
+                    _watcher: watcher,
+                },
+            );
+        }
+    }
+
+    fn load_data(&mut self) {
+        let Some(path) = config_path() else {
+            return;
+        };
+        if !path.exists() {
+            // Older versions kept schedules in a comma-separated ini file.
+            self.load_legacy_ini();
+            return;
+        }
+        let loaded = fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| Ok(serde_json::from_str::<SavedData>(&text)?));
+        match loaded {
+            Ok(data) => {
+                self.schedules = data.schedules;
+                self.settings = data.settings;
+                self.assign_schedule_ids();
+                self.log(format!("Loaded {} schedule(s)", self.schedules.len()));
+            }
+            Err(e) => self.log_at(
+                Level::Error,
+                None,
+                format!("Failed to read {}: {e}", path.display()),
+            ),
+        }
+    }
+
+    fn load_legacy_ini(&mut self) {
+        if let Some(path) = ini_path()
+            && Path::new(&path).exists()
+            && let Ok(file) = File::open(&path)
+        {
+            let mut reader = BufReader::new(file);
+            let mut line = String::new();
+            // first line is title
+            let _ = reader.read_line(&mut line);
+            line.clear();
+            // count line
+            let _ = reader.read_line(&mut line);
+            let count: usize = line.trim().parse().unwrap_or(0);
+            for _ in 0..count {
+                line.clear();
+                if reader.read_line(&mut line).is_err() {
+                    break;
+                }
+                let parts: Vec<_> = line.trim_end().split(',').map(|s| s.to_string()).collect();
+                if parts.len() >= 3 {
+                    let source = parts.first().cloned().unwrap_or_default();
+                    let dest = parts.get(1).cloned().unwrap_or_default();
+                    let period = parts
+                        .get(2)
+                        .and_then(|s| s.parse::<i32>().ok())
+                        .unwrap_or(24);
+                    let skip_files = parts.get(3).cloned().unwrap_or_default();
+                    let skip_folders = parts.get(4).cloned().unwrap_or_default();
+                    let use_zip = parts
+                        .get(5)
+                        .and_then(|s| s.parse::<bool>().ok())
+                        .unwrap_or(false);
+                    self.schedules.push(Schedule::new(
+                        source,
+                        dest,
+                        period,
+                        skip_files,
+                        skip_folders,
+                        use_zip,
+                    ));
+                }
+            }
+            self.assign_schedule_ids();
+            self.log(format!("Loaded {} schedule(s)", self.schedules.len()));
+        }
+    }
+
     fn save_data(&mut self) {
-        if let Some(path) = ini_path() {
-            if let Some(parent) = Path::new(&path).parent() {
+        if let Some(path) = config_path() {
+            if let Some(parent) = path.parent() {
                 let _ = fs::create_dir_all(parent);
             }
 
-            if let Ok(mut f) = File::create(&path) {
-                let _ = writeln!(f, "Count");
-                let _ = writeln!(f, "{}", self.schedules.len());
-                for s in &self.schedules {
-                    let _ = writeln!(
-                        f,
-                        "{},{},{},{},{},{}",
-                        s.source_dir,
-                        s.dest_dir,
-                        s.period_hours,
-                        s.skip_file_exts_label,
-                        s.skip_folders_label,
-                        s.use_zip
-                    );
-                }
+            let data = SavedData {
+                schedules: self.schedules.clone(),
+                settings: self.settings.clone(),
+            };
+            let written = serde_json::to_string_pretty(&data)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| Ok(write_private(&path, &text)?));
+            if let Err(e) = written {
+                self.log_at(
+                    Level::Error,
+                    None,
+                    format!("Failed to save {}: {e}", path.display()),
+                );
             }
         }
     }
@@ -636,26 +2497,57 @@ fn default_backup_root() -> String {
     String::from("./BackUp")
 }
 
-fn default_dest_for_source(source: &str) -> String {
-    let leaf = Path::new(source)
+fn source_leaf(source: &str) -> String {
+    Path::new(source)
         .file_name()
         .map(|s| s.to_string_lossy().to_string())
-        .unwrap_or_else(|| "backup".to_string());
+        .unwrap_or_else(|| "backup".to_string())
+}
+
+fn default_dest_for_source(source: &str) -> String {
     Path::new(&default_backup_root())
-        .join(leaf)
+        .join(source_leaf(source))
         .to_string_lossy()
         .to_string()
 }
 
-fn ini_path() -> Option<PathBuf> {
-    if let Ok(exe) = std::env::current_exe() {
-        if let Some(dir) = exe.parent() {
-            return Some(dir.join("AutoBackup.ini"));
-        }
+fn config_dir() -> Option<PathBuf> {
+    if let Ok(exe) = std::env::current_exe()
+        && let Some(dir) = exe.parent()
+    {
+        return Some(dir.to_path_buf());
     }
     None
 }
 
+fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("AutoBackup.json"))
+}
+
+/// Replace `path` with `text` through a temporary file, readable only by the
+/// current user: the config holds passwords and the API token.
+fn write_private(path: &Path, text: &str) -> std::io::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    // The mode only applies to a new file, so never reuse a leftover.
+    let _ = fs::remove_file(&tmp);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp)?;
+    file.write_all(text.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp, path)
+}
+
+fn ini_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("AutoBackup.ini"))
+}
+
 fn parse_skip_tokens(label: &str) -> Vec<String> {
     // Convert "*.log *.tmp" -> ["log", "tmp"]
     label
@@ -668,121 +2560,471 @@ fn parse_skip_tokens(label: &str) -> Vec<String> {
         .collect()
 }
 
-fn execute_backup(s: &Schedule, tx: Sender<AppMsg>) -> bool {
+/// Applied on the worker thread itself, so the UI keeps its priority.
+#[cfg(target_os = "linux")]
+fn lower_priority_for(s: &Schedule, tx: &Sender<AppMsg>) {
+    if s.throttle.low_priority
+        && let Err(e) = throttle::lower_priority()
+    {
+        let _ = tx.send(AppMsg::Log(
+            Level::Error,
+            format!("Failed to lower priority: {e}"),
+        ));
+    }
+}
+
+/// The setting is not offered elsewhere; one carried over in a config file
+/// from Linux is ignored.
+#[cfg(not(target_os = "linux"))]
+fn lower_priority_for(_s: &Schedule, _tx: &Sender<AppMsg>) {}
+
+/// Run a scheduled or manual backup between the schedule's hooks. The
+/// post-hook runs whatever happened, with the outcome in `AUTOBACKUP_RESULT`.
+fn execute_backup(s: &Schedule, tx: Sender<AppMsg>, report: &mut RunReport) -> Outcome {
+    if !hooks::run(s, Phase::Pre, None, &tx) && s.hooks.abort_on_pre_failure {
+        let _ = tx.send(AppMsg::Log(
+            Level::Warn,
+            format!("{} backup skipped: pre-backup command failed", s.source_dir),
+        ));
+        report.error = Some("Skipped: pre-backup command failed".to_owned());
+        hooks::run(s, Phase::Post, Some("aborted"), &tx);
+        return Outcome::Failed;
+    }
+    let outcome = run_backup(s, &tx, report);
+    let result = if outcome.is_ok() {
+        "success"
+    } else {
+        "failure"
+    };
+    // A failing post-hook does not undo a good backup; it is only logged.
+    hooks::run(s, Phase::Post, Some(result), &tx);
+    outcome
+}
+
+fn run_backup(s: &Schedule, tx: &Sender<AppMsg>, report: &mut RunReport) -> Outcome {
+    let started = Local::now();
     let source = Path::new(&s.source_dir);
-    let dest = Path::new(&s.dest_dir);
 
     if !source.exists() {
-        let _ = tx.send(AppMsg::Log(format!(
-            "Source does not exist: {}",
-            s.source_dir
-        )));
-        return false;
+        return fail(
+            report,
+            tx,
+            format!("Source does not exist: {}", s.source_dir),
+        );
     }
 
-    if !dest.exists() {
-        if let Err(e) = fs::create_dir_all(dest) {
-            let _ = tx.send(AppMsg::Log(format!("Failed to create destination: {e}")));
-            return false;
+    let mut dest = match destination::open(s) {
+        Ok(d) => d,
+        Err(e) => {
+            return fail(
+                report,
+                tx,
+                format!("Failed to open destination {}: {e:#}", s.dest_label()),
+            );
         }
+    };
+
+    // Snapshots are removed when `provider` is dropped at the end of the run.
+    let provider = match source::prepare(s, tx) {
+        Ok(p) => p,
+        Err(e) => {
+            return fail(report, tx, format!("Failed to snapshot source: {e:#}"));
+        }
+    };
+    let source = provider.root();
+
+    let _ = tx.send(AppMsg::Log(
+        Level::Info,
+        format!("{} backup started", s.source_dir),
+    ));
+
+    let opts = CopyOptions::from_schedule(s);
+
+    // Remote destinations receive the archive only; there is no local copy to zip.
+    if s.use_zip && s.dest_kind.is_remote() {
+        let leaf = source_leaf(&s.source_dir);
+        let ok = upload_archive(source, &leaf, dest.as_mut(), &opts, report, tx);
+        save_report(s, dest.as_mut(), started, report, ok, tx);
+        if !ok {
+            return Outcome::Failed;
+        }
+        let _ = tx.send(AppMsg::Log(
+            Level::Info,
+            format!("{} backup completed", s.source_dir),
+        ));
+        return Outcome::Success;
     }
 
-    let _ = tx.send(AppMsg::Log(format!("{} backup started", s.source_dir)));
+    // Checked before the marker goes so a refused run leaves the last
+    // backup marked complete.
+    if s.space_check != SpaceCheck::Off
+        && !s.dest_kind.is_remote()
+        && let Err(msg) = check_free_space(s, source, dest.as_mut(), &opts, tx)
+    {
+        let outcome = fail(report, tx, msg);
+        save_report(s, dest.as_mut(), started, report, false, tx);
+        return outcome;
+    }
 
-    let skip_exts = parse_skip_tokens(&s.skip_file_exts_label);
-    let skip_folders = s
-        .skip_folders_label
-        .split_whitespace()
-        .map(|s| s.to_ascii_lowercase())
-        .collect::<Vec<_>>();
+    // The marker only comes back once this run has finished cleanly.
+    if let Err(e) = destination::clear_complete(dest.as_mut()) {
+        return fail(
+            report,
+            tx,
+            format!("Failed to reset completion marker: {e}"),
+        );
+    }
 
-    // Copy
-    if let Err(e) = copy_recursive(source, dest, &skip_exts, &skip_folders, &tx) {
-        let _ = tx.send(AppMsg::Log(format!("Copy failed: {e}")));
-        return false;
+    let mut journal = Journal::load(s);
+    if journal.done_count() > 0 {
+        let _ = tx.send(AppMsg::Log(
+            Level::Info,
+            format!(
+                "Resuming interrupted backup ({} file(s) already done)",
+                journal.done_count()
+            ),
+        ));
     }
 
-    // Zip
-    if s.use_zip {
-        let ts = Local::now().format("%y%m%d%H");
-        let zip_name = format!("{}_{}.zip", s.dest_dir, ts);
-        let zip_path = Path::new(&zip_name);
-        if zip_path.exists() {
-            let _ = fs::remove_file(zip_path);
+    // Copy
+    let mut copier = Copier::new(dest.as_mut(), &opts, tx).with_journal(&mut journal);
+    let copied = copier.copy_recursive(source, Path::new(""));
+    *report = copier.into_report();
+    if let Err(e) = copied {
+        let outcome = fail(report, tx, format!("Copy failed: {e}"));
+        save_report(s, dest.as_mut(), started, report, false, tx);
+        // Keep what was done so the next run resumes from here
+        if let Err(e) = journal.save() {
+            let _ = tx.send(AppMsg::Log(
+                Level::Error,
+                format!("Failed to save run journal: {e}"),
+            ));
         }
-        let status = Command::new("7z")
-            .arg("a")
-            .arg("-tzip")
-            .arg(&zip_name)
-            .arg(&s.dest_dir)
-            .status();
-        match status {
-            Ok(st) if st.success() => {
-                let _ = tx.send(AppMsg::Log(format!("Zipped to {}", zip_name)));
-            }
-            Ok(st) => {
-                let _ = tx.send(AppMsg::Log(format!("7z exited with status {}", st)));
-            }
-            Err(e) => {
-                let _ = tx.send(AppMsg::Log(format!("Failed to run 7z: {e}")));
-            }
+        return outcome;
+    }
+    journal.remove();
+    for line in report.summary_lines() {
+        let _ = tx.send(AppMsg::Log(Level::Info, line));
+    }
+
+    if report.files_failed == 0 {
+        let contents = format!(
+            "Completed {}\nSource: {}\n",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            s.source_dir
+        );
+        if let Err(e) = destination::mark_complete(dest.as_mut(), &contents) {
+            let _ = tx.send(AppMsg::Log(
+                Level::Error,
+                format!("Failed to write completion marker: {e}"),
+            ));
         }
+    } else {
+        let _ = tx.send(AppMsg::Log(
+            Level::Warn,
+            format!("{} left marked incomplete", dest.describe()),
+        ));
+    }
+    // The report goes last so it covers the archive too.
+    let zip_failed = if s.use_zip {
+        zip_local_backup(s, tx)
+            .err()
+            .map(|msg| fail(report, tx, msg))
+    } else {
+        None
+    };
+    let ok = report.files_failed == 0 && zip_failed.is_none();
+    save_report(s, dest.as_mut(), started, report, ok, tx);
+    if let Some(outcome) = zip_failed {
+        return outcome;
     }
 
-    let _ = tx.send(AppMsg::Log(format!("{} backup completed", s.source_dir)));
+    let _ = tx.send(AppMsg::Log(
+        Level::Info,
+        format!("{} backup completed", s.source_dir),
+    ));
 
-    true
+    if report.files_failed == 0 {
+        Outcome::Success
+    } else {
+        Outcome::Partial {
+            failed: report.files_failed,
+        }
+    }
+}
+
+/// Zip a local backup folder into `<dest_dir>_<timestamp>.zip` beside it.
+fn zip_local_backup(s: &Schedule, tx: &Sender<AppMsg>) -> Result<(), String> {
+    let ts = Local::now().format("%y%m%d%H");
+    let zip_name = format!("{}_{}.zip", s.dest_dir, ts);
+    // 7z writes in place, so the archive only gets its real name once
+    // it is whole.
+    let partial = format!("{zip_name}.partial");
+    let _ = fs::remove_file(&partial);
+    let exclude = [
+        format!("-xr!{}", destination::COMPLETE_MARKER),
+        format!("-xr!{}", report::REPORTS_DIR),
+    ];
+    if !run_7z(Path::new(&partial), Path::new(&s.dest_dir), &exclude, tx) {
+        let _ = fs::remove_file(&partial);
+        return Err(format!("Failed to zip {}", s.dest_dir));
+    }
+    fs::rename(&partial, &zip_name).map_err(|e| format!("Failed to finish {zip_name}: {e}"))
 }
 
-fn copy_recursive(
+/// Compare the bytes this run will write, its archive included, with the
+/// free space at the local destination. Errs with the reason when the run
+/// should not start; when the size or free space cannot be read the run goes
+/// ahead.
+fn check_free_space(
+    s: &Schedule,
     source: &Path,
-    dest: &Path,
-    skip_exts: &[String],
-    skip_folders: &[String],
+    dest: &mut dyn Destination,
+    opts: &CopyOptions,
     tx: &Sender<AppMsg>,
-) -> anyhow::Result<()> {
-    // Ensure destination exists
-    fs::create_dir_all(dest)?;
+) -> Result<(), String> {
+    let dest_dir = Path::new(&s.dest_dir);
+    // The archive goes beside the folder and is at most as big as its files.
+    let zip_dir = s.use_zip.then(|| match dest_dir.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    });
+    let checks = copy::run_size(source, dest, opts).and_then(|size| {
+        let archive = if s.use_zip { size.total } else { 0 };
+        let mut checks = Vec::new();
+        match zip_dir {
+            Some(zip_dir) if !destination::same_filesystem(dest_dir, zip_dir) => {
+                checks.push((dest_dir, size.to_copy, destination::free_space(dest_dir)?));
+                checks.push((zip_dir, archive, destination::free_space(zip_dir)?));
+            }
+            _ => checks.push((
+                dest_dir,
+                size.to_copy + archive,
+                destination::free_space(dest_dir)?,
+            )),
+        }
+        Ok(checks)
+    });
+    let checks = match checks {
+        Ok(checks) => checks,
+        Err(e) => {
+            let _ = tx.send(AppMsg::Log(
+                Level::Warn,
+                format!("Failed to check free space, copying anyway: {e:#}"),
+            ));
+            return Ok(());
+        }
+    };
+    for (dir, needed, free) in checks {
+        let _ = tx.send(AppMsg::Log(
+            Level::Info,
+            format!(
+                "{} to write, {} free at {}",
+                health::format_bytes(needed),
+                health::format_bytes(free),
+                dir.display()
+            ),
+        ));
+        if needed <= free {
+            continue;
+        }
+        let msg = format!(
+            "Not enough space at {}: {} to write but only {} free",
+            dir.display(),
+            health::format_bytes(needed),
+            health::format_bytes(free)
+        );
+        if s.space_check == SpaceCheck::Refuse {
+            return Err(msg);
+        }
+        let _ = tx.send(AppMsg::Log(Level::Warn, format!("{msg}, copying anyway")));
+    }
+    Ok(())
+}
 
-    for entry_res in fs::read_dir(source)? {
-        let entry = entry_res?;
-        let path = entry.path();
-        let file_name = entry.file_name();
-        let file_name_str = file_name.to_string_lossy();
+/// Log why a run stopped early and keep the reason for its summary.
+fn fail(report: &mut RunReport, tx: &Sender<AppMsg>, msg: String) -> Outcome {
+    let _ = tx.send(AppMsg::Log(Level::Error, msg.clone()));
+    report.error = Some(msg);
+    Outcome::Failed
+}
 
-        let dest_path = dest.join(&file_name);
+/// Write the run's report next to the backup and keep it as the schedule's
+/// last report. A failure here does not fail the backup.
+fn save_report(
+    s: &Schedule,
+    dest: &mut dyn Destination,
+    started: DateTime<Local>,
+    report: &RunReport,
+    ok: bool,
+    tx: &Sender<AppMsg>,
+) {
+    let info = RunInfo {
+        name: s.display_name(),
+        source: &s.source_dir,
+        dest: s.dest_label(),
+        started,
+        ok,
+    };
+    let text = report::render(&info, report);
+    if let Err(e) = report::save(dest, s.id, started, &text) {
+        let _ = tx.send(AppMsg::Log(
+            Level::Warn,
+            format!("Failed to save run report: {e:#}"),
+        ));
+    }
+}
 
-        if path.is_dir() {
-            // folder skip check
-            if skip_folders
-                .iter()
-                .any(|f| f.eq_ignore_ascii_case(&file_name_str))
-            {
-                continue;
+/// Back up only `paths` (relative to the source) after a watch event. Deleted
+/// paths are removed from the destination in mirror mode.
+fn execute_changes(s: &Schedule, paths: &[PathBuf], tx: Sender<AppMsg>) -> bool {
+    let source = Path::new(&s.source_dir);
+    let mut dest = match destination::open(s) {
+        Ok(d) => d,
+        Err(e) => {
+            let _ = tx.send(AppMsg::Log(
+                Level::Error,
+                format!("Failed to open destination {}: {e:#}", s.dest_label()),
+            ));
+            return false;
+        }
+    };
+    let opts = CopyOptions::from_schedule(s);
+    let mut copier = Copier::new(dest.as_mut(), &opts, &tx);
+
+    let mut ok = true;
+    for rel in paths {
+        let path = source.join(rel);
+        let result = match fs::symlink_metadata(&path) {
+            Ok(_) => {
+                let parent = rel.parent().unwrap_or(Path::new(""));
+                copier
+                    .dest()
+                    .create_dir_all(parent)
+                    .and_then(|()| copier.copy_entry(&path, rel))
             }
-            copy_recursive(&path, &dest_path, skip_exts, skip_folders, tx)?;
-        } else if path.is_file() {
-            // ext skip
-            let ext = path
-                .extension()
-                .and_then(|e| e.to_str())
-                .unwrap_or("")
-                .to_ascii_lowercase();
-            if !ext.is_empty() && skip_exts.iter().any(|e| e == &ext) {
-                continue;
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let dest = copier.dest();
+                if opts.mirror && dest.stat(rel).is_ok_and(|st| st.is_some()) {
+                    dest.remove(rel).map(|()| {
+                        let _ = tx.send(AppMsg::Log(
+                            Level::Info,
+                            format!("Deleted {}", dest.display_path(rel)),
+                        ));
+                    })
+                } else {
+                    Ok(())
+                }
             }
-            // Copy
-            if let Err(e) = fs::copy(&path, &dest_path) {
-                let _ = tx.send(AppMsg::Log(format!(
-                    "Failed to copy {} -> {}: {}",
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            ok = false;
+            let _ = tx.send(AppMsg::Log(
+                Level::Error,
+                format!(
+                    "Failed to back up {} -> {}: {}",
                     path.display(),
-                    dest_path.display(),
+                    copier.dest().display_path(rel),
                     e
-                )));
-            }
+                ),
+            ));
+        }
+    }
+    ok
+}
+
+fn run_7z(zip_path: &Path, input: &Path, extra_args: &[String], tx: &Sender<AppMsg>) -> bool {
+    let status = Command::new("7z")
+        .arg("a")
+        .arg("-tzip")
+        .args(extra_args)
+        .arg(zip_path)
+        .arg(input)
+        .status();
+    match status {
+        Ok(st) if st.success() => {
+            let _ = tx.send(AppMsg::Log(
+                Level::Info,
+                format!("Zipped to {}", zip_path.display()),
+            ));
+            true
+        }
+        Ok(st) => {
+            let _ = tx.send(AppMsg::Log(
+                Level::Error,
+                format!("7z exited with status {}", st),
+            ));
+            false
+        }
+        Err(e) => {
+            let _ = tx.send(AppMsg::Log(Level::Error, format!("Failed to run 7z: {e}")));
+            false
+        }
+    }
+}
+
+/// Zip the source straight into a temp file, honouring the skip rules, and
+/// push the archive to the destination root.
+/// `leaf` names the archive; `source` may be a snapshot with another name.
+fn upload_archive(
+    source: &Path,
+    leaf: &str,
+    dest: &mut dyn Destination,
+    opts: &CopyOptions,
+    report: &mut RunReport,
+    tx: &Sender<AppMsg>,
+) -> bool {
+    let ts = Local::now().format("%y%m%d%H");
+    let zip_name = format!("{leaf}_{ts}.zip");
+    let local_zip = std::env::temp_dir().join(&zip_name);
+    let _ = fs::remove_file(&local_zip);
+
+    let mut excludes: Vec<String> = opts
+        .skip_exts
+        .iter()
+        .map(|e| format!("-xr!*.{e}"))
+        .collect();
+    excludes.extend(opts.skip_folders.iter().map(|f| format!("-xr!{f}")));
+    if !run_7z(&local_zip, source, &excludes, tx) {
+        report.record(report::Action::Failed, Path::new(&zip_name), None, "7z");
+        return false;
+    }
+
+    let size = fs::metadata(&local_zip).ok().map(|m| m.len());
+    let uploaded = dest.put_file(&local_zip, Path::new(&zip_name));
+    let _ = fs::remove_file(&local_zip);
+    match uploaded {
+        Ok(_) => {
+            report.files_copied += 1;
+            report.bytes_copied += size.unwrap_or(0);
+            report.record(
+                report::Action::Copied,
+                Path::new(&zip_name),
+                size,
+                "archive",
+            );
+            let _ = tx.send(AppMsg::Log(
+                Level::Info,
+                format!("Uploaded {}", dest.display_path(Path::new(&zip_name))),
+            ));
+            true
+        }
+        Err(e) => {
+            report.files_failed += 1;
+            report.record(
+                report::Action::Failed,
+                Path::new(&zip_name),
+                size,
+                &e.to_string(),
+            );
+            let _ = tx.send(AppMsg::Log(
+                Level::Error,
+                format!("Failed to upload {zip_name}: {e}"),
+            ));
+            false
         }
     }
-    Ok(())
 }
 
 impl eframe::App for AppState {
@@ -790,10 +3032,18 @@ impl eframe::App for AppState {
         TopBottomPanel::top("top").show(ctx, |ui| {
             self.ui_top(ui);
         });
+        self.ui_report(ctx);
 
         eframe::egui::CentralPanel::default().show(ctx, |ui| {
             self.ui_table(ui, ctx);
             ui.add_space(10.0);
+            self.ui_health(ui);
+            self.ui_queue(ui);
+            self.ui_email(ui);
+            self.ui_webhook(ui);
+            self.ui_metrics(ui);
+            self.ui_api(ui);
+            ui.add_space(10.0);
             self.ui_logs(ui);
         });
 
@@ -810,6 +3060,13 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "AutoBackup (egui)",
         native_options,
-        Box::new(|_cc| Box::new(AppState::default())),
+        Box::new(|cc| {
+            let mut app = AppState {
+                egui_ctx: Some(cc.egui_ctx.clone()),
+                ..AppState::default()
+            };
+            app.restart_api_server();
+            Box::new(app)
+        }),
     )
 }
@@ -0,0 +1,329 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::http;
+use crate::report::{Outcome, RunSummary};
+use crate::{Schedule, config_dir};
+
+/// Runs kept per schedule for the trends in the Health view.
+const HISTORY_RUNS: usize = 30;
+
+/// Where the Prometheus `/metrics` endpoint listens, if at all.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsSettings {
+    pub enabled: bool,
+    /// `host:port`; use `0.0.0.0:<port>` to allow scraping from other hosts.
+    pub address: String,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:9184".to_owned(),
+        }
+    }
+}
+
+/// What the endpoint reports for one schedule.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduleMetrics {
+    pub name: String,
+    #[serde(skip)]
+    pub running: bool,
+    /// Unix time the last run ended, whatever its result.
+    pub last_run: Option<i64>,
+    /// Unix time the last run without failed files ended.
+    pub last_success: Option<i64>,
+    pub last_duration_secs: f64,
+    pub last_bytes_copied: u64,
+    pub last_files_failed: u64,
+    pub runs_success: u64,
+    pub runs_partial: u64,
+    pub runs_failed: u64,
+    pub bytes_copied_total: u64,
+    pub files_failed_total: u64,
+    /// The latest runs, oldest first.
+    pub history: Vec<RunPoint>,
+}
+
+/// One finished full backup.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunPoint {
+    /// Unix time the run ended.
+    pub finished: i64,
+    pub duration_secs: f64,
+    pub bytes_copied: u64,
+    pub ok: bool,
+    /// Free bytes at a local destination afterwards.
+    pub dest_free: Option<u64>,
+}
+
+/// Per-schedule figures by `Schedule::id`, kept in the config folder so the
+/// last success survives a restart.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metrics {
+    schedules: BTreeMap<u64, ScheduleMetrics>,
+}
+
+impl Metrics {
+    pub fn load() -> Self {
+        metrics_path()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = metrics_path() else {
+            return Ok(());
+        };
+        // Written aside and renamed so a crash mid-save keeps the old figures.
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Add new schedules, rename changed ones and forget deleted ones.
+    pub fn sync(&mut self, schedules: &[Schedule]) {
+        self.schedules
+            .retain(|id, _| schedules.iter().any(|s| s.id == *id));
+        for s in schedules {
+            let entry = self.schedules.entry(s.id).or_default();
+            entry.name = s.display_name();
+            entry.running = s.is_running;
+        }
+    }
+
+    pub fn get(&self, schedule: u64) -> Option<&ScheduleMetrics> {
+        self.schedules.get(&schedule)
+    }
+
+    /// Unix time the last run of `schedule` without failed files ended.
+    pub fn last_success(&self, schedule: u64) -> Option<i64> {
+        self.get(schedule).and_then(|m| m.last_success)
+    }
+
+    pub fn set_running(&mut self, schedule: u64, running: bool) {
+        if let Some(m) = self.schedules.get_mut(&schedule) {
+            m.running = running;
+        }
+    }
+
+    pub fn record(&mut self, schedule: u64, summary: &RunSummary) -> anyhow::Result<()> {
+        let m = self.schedules.entry(schedule).or_default();
+        let finished = summary.finished.timestamp();
+        m.running = false;
+        m.last_run = Some(finished);
+        m.last_duration_secs = summary.duration_secs();
+        m.last_bytes_copied = summary.bytes_copied;
+        m.last_files_failed = summary.files_failed;
+        m.bytes_copied_total += summary.bytes_copied;
+        m.files_failed_total += summary.files_failed;
+        match summary.outcome {
+            Outcome::Success => {
+                m.runs_success += 1;
+                m.last_success = Some(finished);
+            }
+            Outcome::Partial { .. } => m.runs_partial += 1,
+            Outcome::Failed => m.runs_failed += 1,
+        }
+        m.history.push(RunPoint {
+            finished,
+            duration_secs: summary.duration_secs(),
+            bytes_copied: summary.bytes_copied,
+            ok: summary.outcome == Outcome::Success,
+            dest_free: summary.dest_free,
+        });
+        if m.history.len() > HISTORY_RUNS {
+            m.history.remove(0);
+        }
+        self.save()
+    }
+
+    /// The Prometheus text exposition of everything known.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        family(
+            &mut out,
+            &self.schedules,
+            "autobackup_running",
+            "gauge",
+            "Whether a backup of the schedule is running.",
+            |m| Some(u8::from(m.running).to_string()),
+        );
+        family(
+            &mut out,
+            &self.schedules,
+            "autobackup_last_run_timestamp_seconds",
+            "gauge",
+            "Unix time the last full backup ended.",
+            |m| m.last_run.map(|t| t.to_string()),
+        );
+        family(
+            &mut out,
+            &self.schedules,
+            "autobackup_last_success_timestamp_seconds",
+            "gauge",
+            "Unix time the last full backup without errors ended.",
+            |m| m.last_success.map(|t| t.to_string()),
+        );
+        family(
+            &mut out,
+            &self.schedules,
+            "autobackup_last_duration_seconds",
+            "gauge",
+            "Duration of the last full backup.",
+            |m| m.last_run.map(|_| m.last_duration_secs.to_string()),
+        );
+        family(
+            &mut out,
+            &self.schedules,
+            "autobackup_last_bytes_copied",
+            "gauge",
+            "Bytes copied by the last full backup.",
+            |m| m.last_run.map(|_| m.last_bytes_copied.to_string()),
+        );
+        family(
+            &mut out,
+            &self.schedules,
+            "autobackup_last_files_failed",
+            "gauge",
+            "Files the last full backup could not copy.",
+            |m| m.last_run.map(|_| m.last_files_failed.to_string()),
+        );
+        family(
+            &mut out,
+            &self.schedules,
+            "autobackup_bytes_copied_total",
+            "counter",
+            "Bytes copied by full backups.",
+            |m| Some(m.bytes_copied_total.to_string()),
+        );
+        family(
+            &mut out,
+            &self.schedules,
+            "autobackup_files_failed_total",
+            "counter",
+            "Files full backups could not copy.",
+            |m| Some(m.files_failed_total.to_string()),
+        );
+
+        let _ = writeln!(out, "# HELP autobackup_runs_total Full backups by result.");
+        let _ = writeln!(out, "# TYPE autobackup_runs_total counter");
+        for (id, m) in &self.schedules {
+            for (status, count) in [
+                ("success", m.runs_success),
+                ("partial", m.runs_partial),
+                ("failed", m.runs_failed),
+            ] {
+                let _ = writeln!(
+                    out,
+                    "autobackup_runs_total{{{},status=\"{status}\"}} {count}",
+                    labels(*id, m)
+                );
+            }
+        }
+        out
+    }
+}
+
+/// One metric family with a sample per schedule that has a value.
+fn family(
+    out: &mut String,
+    schedules: &BTreeMap<u64, ScheduleMetrics>,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: impl Fn(&ScheduleMetrics) -> Option<String>,
+) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    for (id, m) in schedules {
+        if let Some(v) = value(m) {
+            let _ = writeln!(out, "{name}{{{}}} {v}", labels(*id, m));
+        }
+    }
+}
+
+fn labels(id: u64, m: &ScheduleMetrics) -> String {
+    let name = m
+        .name
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("schedule_id=\"{id}\",schedule=\"{name}\"")
+}
+
+fn metrics_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("metrics.json"))
+}
+
+/// Answer `GET /metrics` at `address` from the shared figures.
+pub fn serve(address: &str, metrics: Arc<Mutex<Metrics>>) -> anyhow::Result<http::Server> {
+    http::Server::start(address, move |request| {
+        if request.url() != "/metrics" {
+            http::respond(request, 404, "text/plain", "Not found".to_owned());
+            return;
+        }
+        let body = metrics.lock().unwrap_or_else(|e| e.into_inner()).render();
+        http::respond(
+            request,
+            200,
+            "text/plain; version=0.0.4; charset=utf-8",
+            body,
+        );
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(name: &str) -> Metrics {
+        let mut m = Metrics::default();
+        m.schedules.insert(
+            7,
+            ScheduleMetrics {
+                name: name.to_owned(),
+                last_run: Some(1_700_000_000),
+                runs_failed: 2,
+                ..Default::default()
+            },
+        );
+        m
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let out = metrics("C:\\Users \"work\"\nfiles").render();
+        assert!(out.contains(
+            "autobackup_last_run_timestamp_seconds{schedule_id=\"7\",\
+             schedule=\"C:\\\\Users \\\"work\\\"\\nfiles\"} 1700000000\n"
+        ));
+        // The newline in the name must not split a sample.
+        assert!(
+            out.lines()
+                .all(|l| l.starts_with("# ") || l.starts_with("autobackup_"))
+        );
+    }
+
+    #[test]
+    fn samples_without_a_value_are_left_out() {
+        let out = metrics("Documents").render();
+        assert!(!out.contains("autobackup_last_success_timestamp_seconds{"));
+        assert!(out.contains(
+            "autobackup_runs_total{schedule_id=\"7\",schedule=\"Documents\",status=\"failed\"} 2\n"
+        ));
+    }
+}
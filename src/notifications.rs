@@ -0,0 +1,150 @@
+use std::sync::mpsc::Sender;
+
+use serde::{Deserialize, Serialize};
+
+use crate::logfile::Level;
+use crate::report::Outcome;
+use crate::{AppMsg, Schedule};
+
+/// Which run results raise a desktop notification.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Notifications {
+    pub on_failure: bool,
+    /// The run finished but some files could not be copied.
+    pub on_errors: bool,
+    pub on_success: bool,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            on_failure: true,
+            on_errors: true,
+            on_success: false,
+        }
+    }
+}
+
+/// Urgency hint of the freedesktop notification spec.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Urgency {
+    Normal = 1,
+    Critical = 2,
+}
+
+/// Notify about a finished run of `s` if its settings ask for it. Failures to
+/// notify are only logged.
+pub fn notify_outcome(s: &Schedule, outcome: Outcome, tx: &Sender<AppMsg>) {
+    let Some((urgency, summary, body)) = notification_for(s, outcome) else {
+        return;
+    };
+    if let Err(e) = send(&summary, &body, urgency) {
+        let _ = tx.send(AppMsg::Log(
+            Level::Warn,
+            format!("Failed to show desktop notification: {e:#}"),
+        ));
+    }
+}
+
+/// Urgency, summary and body of the notification for a run of `s`, if its
+/// settings want one.
+fn notification_for(s: &Schedule, outcome: Outcome) -> Option<(Urgency, String, String)> {
+    let name = s.display_name();
+    let (wanted, urgency, summary, body) = match outcome {
+        Outcome::Failed => (
+            s.notifications.on_failure,
+            Urgency::Critical,
+            format!("Backup failed: {name}"),
+            format!(
+                "{} could not be backed up. See the log for details.",
+                s.source_dir
+            ),
+        ),
+        Outcome::Partial { failed } => (
+            s.notifications.on_errors,
+            Urgency::Critical,
+            format!("Backup finished with errors: {name}"),
+            format!(
+                "{failed} file(s) could not be copied to {}.",
+                s.dest_label()
+            ),
+        ),
+        Outcome::Success => (
+            s.notifications.on_success,
+            Urgency::Normal,
+            format!("Backup completed: {name}"),
+            format!("{} was backed up to {}.", s.source_dir, s.dest_label()),
+        ),
+    };
+    wanted.then_some((urgency, summary, body))
+}
+
+/// Show a notification through `org.freedesktop.Notifications` on the
+/// session bus, returning the id the server assigned.
+#[cfg(target_os = "linux")]
+pub fn send(summary: &str, body: &str, urgency: Urgency) -> anyhow::Result<u32> {
+    use std::collections::HashMap;
+
+    use zbus::zvariant::Value;
+
+    let conn = zbus::blocking::Connection::session()?;
+    let hints = HashMap::from([("urgency", Value::U8(urgency as u8))]);
+    let reply = conn.call_method(
+        Some("org.freedesktop.Notifications"),
+        "/org/freedesktop/Notifications",
+        Some("org.freedesktop.Notifications"),
+        "Notify",
+        &(
+            "AutoBackup",
+            0u32,
+            "drive-harddisk",
+            summary,
+            body,
+            Vec::<&str>::new(),
+            hints,
+            // server default timeout
+            -1i32,
+        ),
+    )?;
+    Ok(reply.body().deserialize::<u32>()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn send(_summary: &str, _body: &str, _urgency: Urgency) -> anyhow::Result<u32> {
+    anyhow::bail!("Desktop notifications are only supported on Linux")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_notify_failures_and_errors_only() {
+        let s = Schedule::default();
+        let (urgency, summary, _) = notification_for(&s, Outcome::Failed).unwrap();
+        assert_eq!(urgency, Urgency::Critical);
+        assert!(summary.starts_with("Backup failed"));
+        let (urgency, _, body) = notification_for(&s, Outcome::Partial { failed: 3 }).unwrap();
+        assert_eq!(urgency, Urgency::Critical);
+        assert!(body.starts_with("3 file(s)"));
+        assert!(notification_for(&s, Outcome::Success).is_none());
+    }
+
+    #[test]
+    fn settings_choose_the_outcomes() {
+        let s = Schedule {
+            notifications: Notifications {
+                on_failure: false,
+                on_errors: false,
+                on_success: true,
+            },
+            ..Default::default()
+        };
+        assert!(notification_for(&s, Outcome::Failed).is_none());
+        assert!(notification_for(&s, Outcome::Partial { failed: 1 }).is_none());
+        let (urgency, summary, _) = notification_for(&s, Outcome::Success).unwrap();
+        assert_eq!(urgency, Urgency::Normal);
+        assert!(summary.starts_with("Backup completed"));
+    }
+}
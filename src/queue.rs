@@ -0,0 +1,299 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// Order in which waiting backups are started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Priority::Low => "Low",
+            Priority::Normal => "Normal",
+            Priority::High => "High",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JobKind {
+    /// The whole source, from the period timer or "Run now".
+    Full,
+    /// Paths (relative to the source) collected by the file watcher.
+    Changes(Vec<PathBuf>),
+}
+
+impl JobKind {
+    pub fn label(&self) -> String {
+        match self {
+            JobKind::Full => "Full backup".to_owned(),
+            JobKind::Changes(paths) => format!("{} changed path(s)", paths.len()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub schedule: usize,
+    pub kind: JobKind,
+    pub priority: Priority,
+    /// Where the job writes; two jobs whose destinations are the same or
+    /// nested never run together.
+    pub dest: PathBuf,
+    pub queued_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug)]
+pub struct RunningJob {
+    pub job: Job,
+    pub started_at: NaiveDateTime,
+}
+
+/// Backups waiting for a free slot and the ones currently running.
+#[derive(Default)]
+pub struct JobQueue {
+    pub waiting: Vec<Job>,
+    pub running: Vec<RunningJob>,
+}
+
+impl JobQueue {
+    pub fn is_waiting(&self, schedule: usize) -> bool {
+        self.waiting.iter().any(|j| j.schedule == schedule)
+    }
+
+    /// Whether a full backup of `schedule` is waiting. Waiting changes do not
+    /// count: `push` turns them into a full backup.
+    pub fn is_waiting_full(&self, schedule: usize) -> bool {
+        self.waiting
+            .iter()
+            .any(|j| j.schedule == schedule && j.kind == JobKind::Full)
+    }
+
+    /// Queue a job. Each schedule has at most one waiting job: watched
+    /// changes are merged into it, and a full backup covers any changes.
+    pub fn push(&mut self, schedule: usize, kind: JobKind, priority: Priority, dest: PathBuf) {
+        if let Some(job) = self.waiting.iter_mut().find(|j| j.schedule == schedule) {
+            match (&mut job.kind, kind) {
+                (JobKind::Changes(have), JobKind::Changes(new)) => {
+                    for path in new {
+                        if !have.contains(&path) {
+                            have.push(path);
+                        }
+                    }
+                }
+                (waiting, JobKind::Full) => *waiting = JobKind::Full,
+                (JobKind::Full, JobKind::Changes(_)) => {}
+            }
+            return;
+        }
+        self.waiting.push(Job {
+            schedule,
+            kind,
+            priority,
+            dest,
+            queued_at: Local::now().naive_local(),
+        });
+    }
+
+    /// Take the next job allowed to start: highest priority first, oldest
+    /// first within a priority, skipping schedules already running and busy
+    /// destinations.
+    pub fn next_ready(&mut self, max_running: usize) -> Option<Job> {
+        if self.running.len() >= max_running {
+            return None;
+        }
+        let pos = self
+            .waiting
+            .iter()
+            .enumerate()
+            .filter(|(_, j)| self.blocked_by(j).is_none())
+            // ties go to the lowest index, i.e. the oldest job
+            .max_by_key(|(i, j)| (j.priority, std::cmp::Reverse(*i)))
+            .map(|(i, _)| i)?;
+        let job = self.waiting.remove(pos);
+        self.running.push(RunningJob {
+            job: job.clone(),
+            started_at: Local::now().naive_local(),
+        });
+        Some(job)
+    }
+
+    /// Why a waiting job cannot start yet, for the queue view.
+    pub fn blocked_by(&self, job: &Job) -> Option<&'static str> {
+        if self.running.iter().any(|r| r.job.schedule == job.schedule) {
+            Some("schedule running")
+        } else if self
+            .running
+            .iter()
+            .any(|r| overlaps(&r.job.dest, &job.dest))
+        {
+            Some("destination busy")
+        } else {
+            None
+        }
+    }
+
+    pub fn finish(&mut self, schedule: usize) {
+        self.running.retain(|r| r.job.schedule != schedule);
+    }
+
+    pub fn remove_waiting(&mut self, pos: usize) -> Option<Job> {
+        (pos < self.waiting.len()).then(|| self.waiting.remove(pos))
+    }
+
+    /// Forget a deleted schedule and renumber the jobs of the ones after it.
+    pub fn remove_schedule(&mut self, schedule: usize) {
+        self.waiting.retain(|j| j.schedule != schedule);
+        for job in &mut self.waiting {
+            if job.schedule > schedule {
+                job.schedule -= 1;
+            }
+        }
+    }
+}
+
+/// Whether one destination is the other or lies inside it.
+fn overlaps(a: &Path, b: &Path) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+/// When a chained schedule runs after the one it depends on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Trigger {
+    #[default]
+    Success,
+    Failure,
+}
+
+impl Trigger {
+    pub const ALL: [Trigger; 2] = [Trigger::Success, Trigger::Failure];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Trigger::Success => "succeeds",
+            Trigger::Failure => "fails",
+        }
+    }
+
+    pub fn matches(self, ok: bool) -> bool {
+        match self {
+            Trigger::Success => ok,
+            Trigger::Failure => !ok,
+        }
+    }
+}
+
+/// Queue a schedule when another one's full backup finishes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dependency {
+    /// `Schedule::id` of the upstream schedule.
+    pub schedule: u64,
+    pub when: Trigger,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(jobs: &[(usize, Priority, &str)]) -> JobQueue {
+        let mut q = JobQueue::default();
+        for &(schedule, priority, dest) in jobs {
+            q.push(schedule, JobKind::Full, priority, PathBuf::from(dest));
+        }
+        q
+    }
+
+    fn changes(paths: &[&str]) -> JobKind {
+        JobKind::Changes(paths.iter().map(PathBuf::from).collect())
+    }
+
+    #[test]
+    fn next_ready_takes_priority_then_age() {
+        let mut q = queue(&[
+            (0, Priority::Normal, "/b/0"),
+            (1, Priority::High, "/b/1"),
+            (2, Priority::Normal, "/b/2"),
+            (3, Priority::High, "/b/3"),
+        ]);
+        let order: Vec<usize> = std::iter::from_fn(|| q.next_ready(10))
+            .map(|j| j.schedule)
+            .collect();
+        assert_eq!(order, [1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn next_ready_respects_the_running_limit() {
+        let mut q = queue(&[(0, Priority::Normal, "/b/0"), (1, Priority::Normal, "/b/1")]);
+        assert_eq!(q.next_ready(1).map(|j| j.schedule), Some(0));
+        assert!(q.next_ready(1).is_none());
+        q.finish(0);
+        assert_eq!(q.next_ready(1).map(|j| j.schedule), Some(1));
+    }
+
+    #[test]
+    fn nested_or_equal_destinations_block_each_other() {
+        let mut q = queue(&[
+            (0, Priority::Normal, "/backup"),
+            (1, Priority::High, "/backup/docs"),
+            (2, Priority::Normal, "/backup-old"),
+        ]);
+        assert_eq!(q.next_ready(10).map(|j| j.schedule), Some(1));
+        assert_eq!(q.blocked_by(&q.waiting[0]), Some("destination busy"));
+        // A sibling whose name merely starts the same is not nested.
+        assert_eq!(q.next_ready(10).map(|j| j.schedule), Some(2));
+        assert!(q.next_ready(10).is_none());
+
+        assert!(overlaps(Path::new("/a/b"), Path::new("/a/b")));
+        assert!(overlaps(Path::new("/a"), Path::new("/a/b/c")));
+        assert!(!overlaps(Path::new("/a/b"), Path::new("/a/c")));
+    }
+
+    #[test]
+    fn a_running_schedule_does_not_start_twice() {
+        let mut q = queue(&[(0, Priority::Normal, "/b/0")]);
+        q.next_ready(10).unwrap();
+        q.push(0, JobKind::Full, Priority::Normal, PathBuf::from("/b/0"));
+        assert_eq!(q.blocked_by(&q.waiting[0]), Some("schedule running"));
+        assert!(q.next_ready(10).is_none());
+    }
+
+    #[test]
+    fn push_merges_changes_and_upgrades_to_full() {
+        let mut q = JobQueue::default();
+        let dest = PathBuf::from("/b");
+        q.push(0, changes(&["a", "b"]), Priority::Normal, dest.clone());
+        q.push(0, changes(&["b", "c"]), Priority::Normal, dest.clone());
+        assert_eq!(q.waiting.len(), 1);
+        assert_eq!(q.waiting[0].kind, changes(&["a", "b", "c"]));
+        assert!(q.is_waiting(0) && !q.is_waiting_full(0));
+
+        q.push(0, JobKind::Full, Priority::Normal, dest.clone());
+        assert_eq!(q.waiting[0].kind, JobKind::Full);
+        q.push(0, changes(&["d"]), Priority::Normal, dest);
+        assert_eq!(q.waiting.len(), 1);
+        assert_eq!(q.waiting[0].kind, JobKind::Full);
+        assert!(q.is_waiting_full(0));
+    }
+
+    #[test]
+    fn remove_schedule_renumbers_later_jobs() {
+        let mut q = queue(&[
+            (0, Priority::Normal, "/b/0"),
+            (1, Priority::Normal, "/b/1"),
+            (2, Priority::Normal, "/b/2"),
+        ]);
+        q.remove_schedule(1);
+        let left: Vec<usize> = q.waiting.iter().map(|j| j.schedule).collect();
+        assert_eq!(left, [0, 1]);
+        assert_eq!(q.waiting[1].dest, PathBuf::from("/b/2"));
+    }
+}
@@ -0,0 +1,328 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+
+use crate::config_dir;
+use crate::destination::{self, Destination};
+
+/// Folder at the destination root holding one report per full run. Mirror
+/// mode and local zips leave it alone.
+pub const REPORTS_DIR: &str = ".auto_backup_reports";
+
+/// Reports kept in `REPORTS_DIR`; older ones are deleted.
+const KEEP_REPORTS: usize = 30;
+
+/// How a full backup run ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    /// Finished, but some files could not be copied.
+    Partial {
+        failed: u64,
+    },
+    Failed,
+}
+
+impl Outcome {
+    pub fn is_ok(self) -> bool {
+        self != Outcome::Failed
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Partial { .. } => "partial",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+/// Errors listed in a `RunSummary`; the report file has all of them.
+const SUMMARY_ERRORS: usize = 20;
+
+/// A finished full run, as sent to the UI thread and the notifiers.
+#[derive(Clone, Debug)]
+pub struct RunSummary {
+    pub run: u64,
+    pub outcome: Outcome,
+    pub started: DateTime<Local>,
+    pub finished: DateTime<Local>,
+    pub files_copied: u64,
+    pub files_unchanged: u64,
+    pub files_failed: u64,
+    pub skipped: usize,
+    pub deleted: usize,
+    pub bytes_copied: u64,
+    /// Free bytes at a local destination once the run ended.
+    pub dest_free: Option<u64>,
+    /// Why the run stopped, then the first failed paths.
+    pub errors: Vec<String>,
+}
+
+impl RunSummary {
+    pub fn new(run: u64, outcome: Outcome, started: DateTime<Local>, report: &RunReport) -> Self {
+        let errors = report
+            .error
+            .iter()
+            .cloned()
+            .chain(
+                report
+                    .manifest
+                    .iter()
+                    .filter(|e| e.action == Action::Failed)
+                    .map(|e| format!("{}: {}", e.path, e.detail)),
+            )
+            .take(SUMMARY_ERRORS)
+            .collect();
+        Self {
+            run,
+            outcome,
+            started,
+            finished: Local::now(),
+            files_copied: report.files_copied,
+            files_unchanged: report.files_unchanged,
+            files_failed: report.files_failed,
+            skipped: report.count(Action::Skipped),
+            deleted: report.count(Action::Deleted),
+            bytes_copied: report.bytes_copied,
+            dest_free: None,
+            errors,
+        }
+    }
+
+    pub fn duration_secs(&self) -> f64 {
+        (self.finished - self.started).num_milliseconds() as f64 / 1000.0
+    }
+}
+
+/// What a run did with one path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Copied,
+    Linked,
+    Skipped,
+    Failed,
+    Deleted,
+}
+
+impl Action {
+    fn label(self) -> &'static str {
+        match self {
+            Action::Copied => "copied",
+            Action::Linked => "linked",
+            Action::Skipped => "skipped",
+            Action::Failed => "failed",
+            Action::Deleted => "deleted",
+        }
+    }
+}
+
+/// One line of a report's file list.
+#[derive(Clone, Debug)]
+pub struct ManifestEntry {
+    pub action: Action,
+    /// Relative to the source (or the destination for deletions).
+    pub path: String,
+    pub size: Option<u64>,
+    /// Skip rule, error, copy strategy or link target.
+    pub detail: String,
+}
+
+/// Counters and notable entries collected while a backup runs.
+#[derive(Clone, Debug, Default)]
+pub struct RunReport {
+    pub files_copied: u64,
+    pub files_unchanged: u64,
+    /// Files skipped because the interrupted run being resumed copied them.
+    pub files_resumed: u64,
+    pub files_failed: u64,
+    pub bytes_copied: u64,
+    /// Files recreated as hard links to an already copied member of their group.
+    pub hard_links: u64,
+    /// Extra copy attempts after a failure or a change during the copy.
+    pub retries: u64,
+    pub reflinked: u64,
+    pub sparse_copies: u64,
+    /// FIFOs, sockets and device nodes, which are not copied.
+    pub special_files: Vec<(String, &'static str)>,
+    /// Files still changing after the last retry. Their copy may be
+    /// inconsistent, e.g. a database caught mid-write.
+    pub changed_files: Vec<String>,
+    /// Every path copied, linked, skipped, failed or deleted. Unchanged and
+    /// resumed files are only counted.
+    pub manifest: Vec<ManifestEntry>,
+    /// Why the run stopped before copying everything.
+    pub error: Option<String>,
+}
+
+impl RunReport {
+    pub fn record(&mut self, action: Action, path: &Path, size: Option<u64>, detail: &str) {
+        self.manifest.push(ManifestEntry {
+            action,
+            path: path.display().to_string(),
+            size,
+            detail: detail.to_owned(),
+        });
+    }
+
+    pub fn record_special(&mut self, path: &Path, rel: &Path, kind: &'static str) {
+        self.special_files.push((path.display().to_string(), kind));
+        self.record(Action::Skipped, rel, None, kind);
+    }
+
+    pub fn count(&self, action: Action) -> usize {
+        self.manifest.iter().filter(|e| e.action == action).count()
+    }
+
+    pub fn record_changed(&mut self, path: &Path) {
+        self.changed_files.push(path.display().to_string());
+    }
+
+    pub fn summary_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Copied {} file(s) ({} bytes), {} unchanged, {} failed, {} hard link(s)",
+            self.files_copied,
+            self.bytes_copied,
+            self.files_unchanged,
+            self.files_failed,
+            self.hard_links
+        )];
+        let (skipped, deleted) = (self.count(Action::Skipped), self.count(Action::Deleted));
+        if skipped > 0 || deleted > 0 {
+            lines.push(format!("Skipped {skipped} path(s), deleted {deleted}"));
+        }
+        if self.files_resumed > 0 {
+            lines.push(format!(
+                "Resumed: {} file(s) already copied by the interrupted run",
+                self.files_resumed
+            ));
+        }
+        if self.files_copied > 0 {
+            lines.push(format!(
+                "Copy strategy: {} reflink, {} sparse, {} full copy",
+                self.reflinked,
+                self.sparse_copies,
+                self.files_copied - self.reflinked - self.sparse_copies
+            ));
+        }
+        if self.retries > 0 {
+            lines.push(format!("Retried {} time(s)", self.retries));
+        }
+        if !self.changed_files.is_empty() {
+            lines.push(format!(
+                "Files changed during backup ({}), copies may be inconsistent:",
+                self.changed_files.len()
+            ));
+            for path in &self.changed_files {
+                lines.push(format!("  {path}"));
+            }
+        }
+        if !self.special_files.is_empty() {
+            lines.push(format!(
+                "Skipped {} special file(s):",
+                self.special_files.len()
+            ));
+            for (path, kind) in &self.special_files {
+                lines.push(format!("  {kind}: {path}"));
+            }
+        }
+        lines
+    }
+}
+
+/// How a finished run went, for the report header.
+pub struct RunInfo<'a> {
+    pub name: String,
+    pub source: &'a str,
+    pub dest: String,
+    pub started: DateTime<Local>,
+    pub ok: bool,
+}
+
+/// Plain-text report: header, totals, then the file list.
+pub fn render(info: &RunInfo, report: &RunReport) -> String {
+    let finished = Local::now();
+    let secs = (finished - info.started).num_milliseconds() as f64 / 1000.0;
+    let mut text = String::new();
+    let _ = writeln!(text, "Backup report: {}", info.name);
+    let _ = writeln!(text, "Source:      {}", info.source);
+    let _ = writeln!(text, "Destination: {}", info.dest);
+    let _ = writeln!(
+        text,
+        "Started:     {}",
+        info.started.format("%Y-%m-%d %H:%M:%S")
+    );
+    let _ = writeln!(
+        text,
+        "Finished:    {}",
+        finished.format("%Y-%m-%d %H:%M:%S")
+    );
+    let _ = writeln!(text, "Duration:    {secs:.1}s");
+    let _ = writeln!(
+        text,
+        "Result:      {}",
+        if info.ok { "success" } else { "failure" }
+    );
+    text.push('\n');
+    for line in report.summary_lines() {
+        let _ = writeln!(text, "{line}");
+    }
+    if !report.manifest.is_empty() {
+        let _ = writeln!(text, "\nFiles:");
+    }
+    for entry in &report.manifest {
+        let size = entry.size.map_or_else(|| "-".to_owned(), |s| s.to_string());
+        let _ = write!(
+            text,
+            "{:<8} {:>14}  {}",
+            entry.action.label(),
+            size,
+            entry.path
+        );
+        if !entry.detail.is_empty() {
+            let _ = write!(text, "  ({})", entry.detail);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Where the app keeps the latest report of a schedule, so it can be shown
+/// without reaching the destination.
+pub fn last_report_path(schedule_id: u64) -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("reports").join(format!("{schedule_id}.txt")))
+}
+
+/// Store `text` in the destination's report folder and as the schedule's
+/// last report, dropping the oldest reports beyond `KEEP_REPORTS`.
+pub fn save(
+    dest: &mut dyn Destination,
+    schedule_id: u64,
+    started: DateTime<Local>,
+    text: &str,
+) -> anyhow::Result<()> {
+    if let Some(path) = last_report_path(schedule_id) {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, text)?;
+    }
+
+    let dir = Path::new(REPORTS_DIR);
+    dest.create_dir_all(dir)?;
+    let name = format!("{}.txt", started.format("%Y%m%d-%H%M%S"));
+    destination::put_text(dest, &dir.join(name), text)?;
+    // Timestamped names sort oldest first.
+    let mut reports: Vec<String> = dest
+        .list_dir(dir)?
+        .into_iter()
+        .filter(|n| n.ends_with(".txt"))
+        .collect();
+    reports.sort();
+    let excess = reports.len().saturating_sub(KEEP_REPORTS);
+    for old in &reports[..excess] {
+        dest.remove(&dir.join(old))?;
+    }
+    Ok(())
+}
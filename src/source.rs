@@ -0,0 +1,315 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::logfile::Level;
+use crate::{AppMsg, Schedule};
+
+/// Name prefix of the snapshots we create. Folders starting with it are never
+/// backed up or watched, so leftovers from a crashed run stay out of backups.
+pub const SNAPSHOT_PREFIX: &str = ".auto_backup_snapshot";
+
+/// How the source is frozen before a backup reads it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SnapshotMode {
+    /// Read the live folder.
+    #[default]
+    None,
+    /// Read-only snapshot of the Btrfs subvolume holding the source.
+    Btrfs,
+    /// Snapshot of the LVM logical volume holding the source, mounted
+    /// read-only for the run.
+    Lvm,
+}
+
+impl SnapshotMode {
+    pub const ALL: [SnapshotMode; 3] = [SnapshotMode::None, SnapshotMode::Btrfs, SnapshotMode::Lvm];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SnapshotMode::None => "No snapshot",
+            SnapshotMode::Btrfs => "Btrfs snapshot",
+            SnapshotMode::Lvm => "LVM snapshot",
+        }
+    }
+}
+
+/// The tree a backup reads from. Snapshot providers remove their snapshot
+/// when dropped.
+pub trait SourceProvider {
+    /// Folder holding the same tree as `Schedule::source_dir`.
+    fn root(&self) -> &Path;
+}
+
+struct LiveSource(PathBuf);
+
+impl SourceProvider for LiveSource {
+    fn root(&self) -> &Path {
+        &self.0
+    }
+}
+
+/// Provide the source of `s` according to its snapshot mode.
+pub fn prepare(s: &Schedule, tx: &Sender<AppMsg>) -> anyhow::Result<Box<dyn SourceProvider>> {
+    let source = PathBuf::from(&s.source_dir);
+    match s.snapshot {
+        SnapshotMode::None => Ok(Box::new(LiveSource(source))),
+        SnapshotMode::Btrfs => Ok(Box::new(BtrfsSnapshot::create(&source, tx)?)),
+        SnapshotMode::Lvm => Ok(Box::new(LvmSnapshot::create(&source, tx)?)),
+    }
+}
+
+/// Unique name for a snapshot taken by this process.
+fn snapshot_name() -> String {
+    static SEQ: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "{SNAPSHOT_PREFIX}-{}-{}",
+        std::process::id(),
+        SEQ.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Run a system tool, turning a non-zero exit into an error carrying its
+/// stderr.
+fn run_tool(program: &str, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {program}"))?;
+    if !output.status.success() {
+        bail!(
+            "{program} {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+struct BtrfsSnapshot {
+    snapshot: PathBuf,
+    root: PathBuf,
+    tx: Sender<AppMsg>,
+}
+
+impl BtrfsSnapshot {
+    /// Snapshot the subvolume holding `source` into a hidden folder at the
+    /// top of that subvolume, the only place guaranteed to be on the same
+    /// filesystem.
+    fn create(source: &Path, tx: &Sender<AppMsg>) -> anyhow::Result<Self> {
+        let source = source.canonicalize()?;
+        let subvolume = btrfs_subvolume_of(&source)?;
+        let snapshot = subvolume.join(snapshot_name());
+        run_tool(
+            "btrfs",
+            &[
+                "subvolume",
+                "snapshot",
+                "-r",
+                &subvolume.to_string_lossy(),
+                &snapshot.to_string_lossy(),
+            ],
+        )?;
+        let root = snapshot.join(source.strip_prefix(&subvolume)?);
+        let _ = tx.send(AppMsg::Log(
+            Level::Info,
+            format!("Created Btrfs snapshot {}", snapshot.display()),
+        ));
+        Ok(Self {
+            snapshot,
+            root,
+            tx: tx.clone(),
+        })
+    }
+}
+
+impl SourceProvider for BtrfsSnapshot {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Drop for BtrfsSnapshot {
+    fn drop(&mut self) {
+        let (level, msg) = match run_tool(
+            "btrfs",
+            &["subvolume", "delete", &self.snapshot.to_string_lossy()],
+        ) {
+            Ok(_) => (
+                Level::Info,
+                format!("Deleted Btrfs snapshot {}", self.snapshot.display()),
+            ),
+            Err(e) => (
+                Level::Error,
+                format!("Failed to delete Btrfs snapshot: {e}"),
+            ),
+        };
+        let _ = self.tx.send(AppMsg::Log(level, msg));
+    }
+}
+
+/// The subvolume root holding `path`: the nearest ancestor whose inode is
+/// 256, the number every Btrfs subvolume root has.
+#[cfg(target_os = "linux")]
+fn btrfs_subvolume_of(path: &Path) -> anyhow::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    const BTRFS_SUPER_MAGIC: libc::c_long = 0x9123_683e;
+    const BTRFS_FIRST_FREE_OBJECTID: u64 = 256;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_encoded_bytes())?;
+    // SAFETY: `stat` is plain data and statfs(2) only writes into it.
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // f_type is a different integer type on different targets
+    #[allow(clippy::unnecessary_cast)]
+    if stat.f_type as libc::c_long != BTRFS_SUPER_MAGIC {
+        bail!("{} is not on a Btrfs filesystem", path.display());
+    }
+    let dev = path.metadata()?.dev();
+    for dir in path.ancestors() {
+        let meta = dir.metadata()?;
+        if meta.dev() != dev {
+            break;
+        }
+        if meta.ino() == BTRFS_FIRST_FREE_OBJECTID {
+            return Ok(dir.to_path_buf());
+        }
+    }
+    bail!("No Btrfs subvolume found above {}", path.display())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn btrfs_subvolume_of(_path: &Path) -> anyhow::Result<PathBuf> {
+    bail!("Btrfs snapshots are only supported on Linux")
+}
+
+struct LvmSnapshot {
+    /// `vg/lv` of the snapshot volume.
+    volume: String,
+    mount_dir: PathBuf,
+    /// Whether `mount_dir` holds the snapshot yet.
+    mounted: bool,
+    root: PathBuf,
+    tx: Sender<AppMsg>,
+}
+
+/// One line of `findmnt -J`.
+#[derive(Deserialize)]
+struct MountInfo {
+    source: String,
+    target: PathBuf,
+    fstype: String,
+}
+
+#[derive(Deserialize)]
+struct FindmntOutput {
+    filesystems: Vec<MountInfo>,
+}
+
+impl LvmSnapshot {
+    /// Snapshot the logical volume mounted under `source` and mount it
+    /// read-only in a temporary folder.
+    fn create(source: &Path, tx: &Sender<AppMsg>) -> anyhow::Result<Self> {
+        let source = source.canonicalize()?;
+        // JSON, as the plain output escapes spaces in mount points.
+        let mount = run_tool(
+            "findmnt",
+            &[
+                "-J",
+                "-o",
+                "SOURCE,TARGET,FSTYPE",
+                "-T",
+                &source.to_string_lossy(),
+            ],
+        )?;
+        let Some(MountInfo {
+            source: device,
+            target,
+            fstype,
+        }) = serde_json::from_str::<FindmntOutput>(&mount)?
+            .filesystems
+            .into_iter()
+            .next()
+        else {
+            bail!("Cannot find the mount holding {}", source.display());
+        };
+        let rel = source.strip_prefix(&target)?.to_path_buf();
+        let vg_lv = run_tool("lvs", &["--noheadings", "-o", "vg_name,lv_name", &device])
+            .with_context(|| format!("{device} is not an LVM logical volume"))?;
+        let mut names = vg_lv.split_whitespace();
+        let (Some(vg), Some(lv)) = (names.next(), names.next()) else {
+            bail!("{device} is not an LVM logical volume");
+        };
+
+        let name = snapshot_name().trim_start_matches('.').to_owned();
+        run_tool(
+            "lvcreate",
+            &["-s", "-l", "10%ORIGIN", "-n", &name, &format!("{vg}/{lv}")],
+        )?;
+        // From here on dropping the guard removes the snapshot again.
+        let mount_dir = std::env::temp_dir().join(&name);
+        let mut snapshot = Self {
+            volume: format!("{vg}/{name}"),
+            root: mount_dir.join(rel),
+            mount_dir,
+            mounted: false,
+            tx: tx.clone(),
+        };
+        std::fs::create_dir_all(&snapshot.mount_dir)?;
+        // XFS refuses a second mount of the same filesystem UUID.
+        let options = if fstype == "xfs" { "ro,nouuid" } else { "ro" };
+        run_tool(
+            "mount",
+            &[
+                "-o",
+                options,
+                &format!("/dev/{}", snapshot.volume),
+                &snapshot.mount_dir.to_string_lossy(),
+            ],
+        )?;
+        snapshot.mounted = true;
+
+        let _ = tx.send(AppMsg::Log(
+            Level::Info,
+            format!(
+                "Created LVM snapshot {} at {}",
+                snapshot.volume,
+                snapshot.mount_dir.display()
+            ),
+        ));
+        Ok(snapshot)
+    }
+}
+
+impl SourceProvider for LvmSnapshot {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Drop for LvmSnapshot {
+    fn drop(&mut self) {
+        let unmounted = if self.mounted {
+            run_tool("umount", &[&self.mount_dir.to_string_lossy()]).map(drop)
+        } else {
+            Ok(())
+        };
+        let removed = unmounted.and_then(|()| run_tool("lvremove", &["-f", &self.volume]));
+        let (level, msg) = match removed {
+            Ok(_) => (Level::Info, format!("Removed LVM snapshot {}", self.volume)),
+            Err(e) => (
+                Level::Error,
+                format!("Failed to remove LVM snapshot {}: {e}", self.volume),
+            ),
+        };
+        let _ = std::fs::remove_dir(&self.mount_dir);
+        let _ = self.tx.send(AppMsg::Log(level, msg));
+    }
+}
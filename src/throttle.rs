@@ -0,0 +1,264 @@
+use std::io::{self, Read};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, Timelike};
+use serde::{Deserialize, Serialize};
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// Throughput limit in force between two hours of the day.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RateProfile {
+    pub from_hour: u32,
+    /// Exclusive. Smaller than `from_hour` for windows crossing midnight.
+    pub to_hour: u32,
+    /// MB/s, 0 for unlimited.
+    pub limit_mbps: f64,
+}
+
+impl RateProfile {
+    fn covers(&self, hour: u32) -> bool {
+        if self.from_hour == self.to_hour {
+            true
+        } else if self.from_hour < self.to_hour {
+            (self.from_hour..self.to_hour).contains(&hour)
+        } else {
+            hour >= self.from_hour || hour < self.to_hour
+        }
+    }
+}
+
+/// Per-schedule throttling settings.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Throttle {
+    /// MB/s outside every profile, 0 for unlimited.
+    pub limit_mbps: f64,
+    pub profiles: Vec<RateProfile>,
+    /// Run the backup thread at the lowest CPU and I/O priority.
+    pub low_priority: bool,
+}
+
+impl Throttle {
+    /// Limit for the given hour: the first matching profile, else the
+    /// default.
+    fn limit_at(&self, hour: u32) -> f64 {
+        self.profiles
+            .iter()
+            .find(|p| p.covers(hour))
+            .map_or(self.limit_mbps, |p| p.limit_mbps)
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.limit_mbps > 0.0 || self.profiles.iter().any(|p| p.limit_mbps > 0.0)
+    }
+
+    /// e.g. `9-18=5 22-6=0`
+    pub fn profiles_label(&self) -> String {
+        self.profiles
+            .iter()
+            .map(|p| format!("{}-{}={}", p.from_hour, p.to_hour, p.limit_mbps))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Parse `from-to=MB/s` entries separated by spaces, hours 0-24.
+pub fn parse_profiles(label: &str) -> Result<Vec<RateProfile>, String> {
+    label
+        .split_whitespace()
+        .map(|token| {
+            let invalid = || format!("Invalid rate profile \"{token}\", expected e.g. 9-18=5");
+            let (hours, limit) = token.split_once('=').ok_or_else(invalid)?;
+            let (from, to) = hours.split_once('-').ok_or_else(invalid)?;
+            let from: u32 = from.trim().parse().map_err(|_| invalid())?;
+            let to: u32 = to.trim().parse().map_err(|_| invalid())?;
+            let limit_mbps: f64 = limit.trim().parse().map_err(|_| invalid())?;
+            if from > 24 || to > 24 || !limit_mbps.is_finite() || limit_mbps < 0.0 {
+                return Err(invalid());
+            }
+            // 24 is the same hour as 0, so "0-24" is the whole day
+            Ok(RateProfile {
+                from_hour: from % 24,
+                to_hour: to % 24,
+                limit_mbps,
+            })
+        })
+        .collect()
+}
+
+struct Budget {
+    since: Instant,
+    bytes: u64,
+}
+
+/// Keeps a destination's average throughput under the schedule's current
+/// limit by sleeping after reads that got ahead of it.
+pub struct Limiter {
+    settings: Throttle,
+    budget: Mutex<Budget>,
+}
+
+impl Limiter {
+    pub fn new(settings: &Throttle) -> Option<Self> {
+        settings.is_limited().then(|| Self {
+            settings: settings.clone(),
+            budget: Mutex::new(Budget {
+                since: Instant::now(),
+                bytes: 0,
+            }),
+        })
+    }
+
+    /// Account for `bytes` just transferred, sleeping as long as needed.
+    pub fn consume(&self, bytes: u64) {
+        let limit = self.settings.limit_at(Local::now().hour());
+        let mut budget = self.budget.lock().unwrap_or_else(|e| e.into_inner());
+        if limit <= 0.0 {
+            budget.since = Instant::now();
+            budget.bytes = 0;
+            return;
+        }
+        budget.bytes += bytes;
+        let due = Duration::from_secs_f64(budget.bytes as f64 / (limit * MIB));
+        let elapsed = budget.since.elapsed();
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        } else if elapsed - due > Duration::from_secs(1) {
+            // Idle time (stat calls, a quiet source) is not saved up for a
+            // burst later.
+            budget.since = Instant::now();
+            budget.bytes = 0;
+        }
+    }
+}
+
+/// Reader that charges everything it reads to a `Limiter`.
+pub struct Throttled<'a, R> {
+    inner: R,
+    limiter: Option<&'a Limiter>,
+}
+
+impl<'a, R> Throttled<'a, R> {
+    pub fn new(inner: R, limiter: Option<&'a Limiter>) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+impl<R: Read> Read for Throttled<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(limiter) = self.limiter {
+            limiter.consume(n as u64);
+        }
+        Ok(n)
+    }
+}
+
+/// Drop the calling thread to the lowest CPU priority and the best-effort
+/// I/O class's lowest level, like `nice -n 19 ionice -c2 -n7`. Child
+/// processes started by the thread (hooks, 7z) inherit both.
+#[cfg(target_os = "linux")]
+pub fn lower_priority() -> io::Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+    // SAFETY: plain syscalls on the current thread id.
+    unsafe {
+        let tid = libc::gettid();
+        if libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, 19) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ioprio = (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | 7;
+        if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, ioprio) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(from_hour: u32, to_hour: u32) -> RateProfile {
+        RateProfile {
+            from_hour,
+            to_hour,
+            limit_mbps: 1.0,
+        }
+    }
+
+    #[test]
+    fn covers_daytime_and_overnight_windows() {
+        let day = profile(9, 18);
+        assert!(day.covers(9) && day.covers(17));
+        assert!(!day.covers(18) && !day.covers(8));
+
+        let night = profile(22, 6);
+        assert!(night.covers(22) && night.covers(23) && night.covers(0) && night.covers(5));
+        assert!(!night.covers(6) && !night.covers(12) && !night.covers(21));
+
+        let all_day = profile(0, 0);
+        assert!((0..24).all(|h| all_day.covers(h)));
+    }
+
+    #[test]
+    fn first_matching_profile_wins() {
+        let throttle = Throttle {
+            limit_mbps: 2.0,
+            profiles: parse_profiles("9-18=5 12-13=0").unwrap(),
+            low_priority: false,
+        };
+        assert_eq!(throttle.limit_at(12), 5.0);
+        assert_eq!(throttle.limit_at(20), 2.0);
+    }
+
+    #[test]
+    fn parses_profiles() {
+        assert_eq!(
+            parse_profiles(" 9-18=5  22-6=0.5 0-24=1 ").unwrap(),
+            [
+                RateProfile {
+                    from_hour: 9,
+                    to_hour: 18,
+                    limit_mbps: 5.0
+                },
+                RateProfile {
+                    from_hour: 22,
+                    to_hour: 6,
+                    limit_mbps: 0.5
+                },
+                RateProfile {
+                    from_hour: 0,
+                    to_hour: 0,
+                    limit_mbps: 1.0
+                },
+            ]
+        );
+        assert_eq!(parse_profiles("").unwrap(), []);
+        let throttle = Throttle {
+            profiles: parse_profiles("22-6=0.5").unwrap(),
+            ..Throttle::default()
+        };
+        assert_eq!(
+            parse_profiles(&throttle.profiles_label()).unwrap(),
+            throttle.profiles
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_profiles() {
+        for label in [
+            "9-18", "9=5", "9-18=", "a-18=5", "9-25=5", "9-18=-1", "9-18=NaN", "9-18=inf", "-3-4=1",
+        ] {
+            let err = parse_profiles(label).unwrap_err();
+            assert!(err.starts_with("Invalid rate profile"), "{label}: {err}");
+        }
+        // One bad entry rejects the whole list.
+        assert!(parse_profiles("9-18=5 oops").is_err());
+    }
+}
@@ -0,0 +1,43 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+use notify::event::EventKind;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::copy::CopyOptions;
+use crate::destination::DestKind;
+use crate::{AppMsg, Schedule};
+
+/// Watch `s.source_dir` and forward changed paths (relative to the source) as
+/// `AppMsg::SourceChanged`. Paths hit by the skip rules never leave the
+/// watcher thread.
+pub fn start(s: &Schedule, tx: Sender<AppMsg>) -> anyhow::Result<RecommendedWatcher> {
+    let id = s.id;
+    let source = PathBuf::from(&s.source_dir);
+    let opts = CopyOptions::from_schedule(s);
+    // A local destination inside the source would otherwise trigger itself.
+    let own_dest = (s.dest_kind == DestKind::Local).then(|| PathBuf::from(&s.dest_dir));
+
+    let root = source.clone();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        let Ok(event) = res else {
+            return;
+        };
+        if matches!(event.kind, EventKind::Access(_) | EventKind::Other) {
+            return;
+        }
+        let changed: Vec<PathBuf> = event
+            .paths
+            .iter()
+            .filter(|p| !own_dest.as_deref().is_some_and(|d| p.starts_with(d)))
+            .filter_map(|p| p.strip_prefix(&root).ok())
+            .filter(|rel| !rel.as_os_str().is_empty() && !opts.is_skipped(rel))
+            .map(Path::to_path_buf)
+            .collect();
+        if !changed.is_empty() {
+            let _ = tx.send(AppMsg::SourceChanged(id, changed));
+        }
+    })?;
+    watcher.watch(&source, RecursiveMode::Recursive)?;
+    Ok(watcher)
+}
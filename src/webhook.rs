@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::Schedule;
+use crate::report::{Outcome, RunSummary};
+
+/// Endpoint receiving a JSON document for run events, shared by all
+/// schedules.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookSettings {
+    /// Empty to disable.
+    pub url: String,
+    /// Extra request header, e.g. `Authorization: Bearer <token>`.
+    pub header: String,
+    pub on_start: bool,
+    pub on_success: bool,
+    /// Also covers runs that finished with failed files.
+    pub on_failure: bool,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            header: String::new(),
+            on_start: false,
+            on_success: true,
+            on_failure: true,
+        }
+    }
+}
+
+impl WebhookSettings {
+    pub fn is_configured(&self) -> bool {
+        !self.url.trim().is_empty()
+    }
+
+    pub fn wants(&self, outcome: Outcome) -> bool {
+        self.is_configured()
+            && match outcome {
+                Outcome::Success => self.on_success,
+                Outcome::Partial { .. } | Outcome::Failed => self.on_failure,
+            }
+    }
+
+    /// POST `payload`, treating any non-2xx answer as an error.
+    pub fn post(&self, payload: &Value) -> anyhow::Result<()> {
+        let mut request = ureq::post(self.url.trim())
+            .timeout(Duration::from_secs(30))
+            .set("Content-Type", "application/json");
+        if let Some((name, value)) = self.header.split_once(':') {
+            request = request.set(name.trim(), value.trim());
+        }
+        request.send_string(&payload.to_string())?;
+        Ok(())
+    }
+}
+
+fn schedule_json(s: &Schedule) -> Value {
+    json!({
+        "id": s.id,
+        "name": s.display_name(),
+        "source": s.source_dir,
+        "destination": s.dest_label(),
+    })
+}
+
+pub fn started_payload(s: &Schedule, run: u64, started: DateTime<Local>) -> Value {
+    json!({
+        "event": "backup.started",
+        "schedule": schedule_json(s),
+        "run_id": run,
+        "started_at": started.to_rfc3339(),
+    })
+}
+
+pub fn finished_payload(s: &Schedule, summary: &RunSummary) -> Value {
+    json!({
+        "event": "backup.finished",
+        "schedule": schedule_json(s),
+        "run_id": summary.run,
+        "status": summary.outcome.label(),
+        "started_at": summary.started.to_rfc3339(),
+        "finished_at": summary.finished.to_rfc3339(),
+        "duration_secs": summary.duration_secs(),
+        "counts": {
+            "copied": summary.files_copied,
+            "unchanged": summary.files_unchanged,
+            "failed": summary.files_failed,
+            "skipped": summary.skipped,
+            "deleted": summary.deleted,
+        },
+        "bytes_copied": summary.bytes_copied,
+        "error_summary": summary.errors.first(),
+        "errors": summary.errors,
+    })
+}